@@ -0,0 +1,145 @@
+use core::ops::Range;
+
+use pulldown_cmark_wikilink::{Event, Tag, TagEnd};
+
+/// a line delimiting a container, like `:::details Title` or `:::`
+#[derive(Debug, PartialEq)]
+pub enum Marker {
+    /// the start of a container, with the name and the rest of the line
+    Open { name: String, title: String },
+
+    /// the end of the last container
+    Close,
+}
+
+/// `parse_marker(line)` parses a line starting with `:::`,
+/// that opens or closes a container
+pub fn parse_marker(line: &str) -> Option<Marker> {
+    let rest = line.trim().strip_prefix(":::")?.trim();
+    if rest.is_empty() {
+        return Some(Marker::Close);
+    }
+    let (name, title) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return None;
+    }
+    Some(Marker::Open {
+        name: name.to_string(),
+        title: title.trim().to_string(),
+    })
+}
+
+/// `marker_text(events)` gives the text of the events of a paragraph
+/// if they only contain a container marker
+pub fn marker_text<'a>(events: &'a [(Event, Range<usize>)]) -> Option<&'a str> {
+    match events {
+        [(Event::Text(s), _)] if parse_marker(s).is_some() => Some(s),
+        _ => None,
+    }
+}
+
+/// `split_markers(events)` moves the container markers to their own paragraph.
+/// pulldown_cmark does not know about containers, so in
+/// ```markdown
+/// :::details Title
+/// some text
+/// :::
+/// ```
+/// the markers are in the same paragraph as the text
+pub fn split_markers(events: Vec<(Event, Range<usize>)>) -> Vec<(Event, Range<usize>)> {
+    let mut result = Vec::with_capacity(events.len());
+    // the start of the current paragraph, and its content
+    let mut paragraph: Option<(Range<usize>, Vec<_>)> = None;
+
+    for (event, range) in events {
+        match (&event, &mut paragraph) {
+            (Event::Start(Tag::Paragraph), None) => paragraph = Some((range, Vec::new())),
+            (Event::End(TagEnd::Paragraph), Some(_)) => {
+                let (start, content) = paragraph.take().unwrap();
+                split_paragraph(&mut result, start, content, range)
+            }
+            (_, Some((_, content))) => content.push((event, range)),
+            (_, None) => result.push((event, range)),
+        }
+    }
+    result
+}
+
+type Line<'a> = Vec<(Event<'a>, Range<usize>)>;
+
+/// `split_paragraph(result, start, content, end)` pushes the paragraph made of `content`
+/// to `result`, split at the lines that are container markers
+fn split_paragraph<'a>(
+    result: &mut Vec<(Event<'a>, Range<usize>)>,
+    start: Range<usize>,
+    content: Vec<(Event<'a>, Range<usize>)>,
+    end: Range<usize>,
+) {
+    // each line keeps the soft break that ends it
+    let mut lines: Vec<Line<'a>> = vec![Vec::new()];
+    for (event, range) in content {
+        let is_break = event == Event::SoftBreak;
+        lines.last_mut().unwrap().push((event, range));
+        if is_break {
+            lines.push(Vec::new())
+        }
+    }
+
+    let markers: Vec<Option<(String, Range<usize>)>> = lines.iter().map(line_marker).collect();
+    if markers.iter().all(Option::is_none) {
+        result.push((Event::Start(Tag::Paragraph), start));
+        result.extend(lines.into_iter().flatten());
+        result.push((Event::End(TagEnd::Paragraph), end));
+        return;
+    }
+
+    let mut text: Line<'a> = Vec::new();
+    for (line, marker) in lines.into_iter().zip(markers) {
+        match marker {
+            Some((marker, range)) => {
+                push_paragraph(result, std::mem::take(&mut text));
+                push_paragraph(result, vec![(Event::Text(marker.into()), range)]);
+            }
+            None => text.extend(line),
+        }
+    }
+    push_paragraph(result, text);
+}
+
+/// `line_marker(line)` gives the text and the range of `line`
+/// if it is a container marker
+fn line_marker(line: &Line) -> Option<(String, Range<usize>)> {
+    let mut text = String::new();
+    let mut range: Option<Range<usize>> = None;
+    for (event, r) in line {
+        match event {
+            Event::Text(s) => text.push_str(s),
+            Event::SoftBreak => continue,
+            _ => return None,
+        }
+        range = Some(range.map_or(r.clone(), |x| x.start..r.end));
+    }
+    parse_marker(&text)?;
+    Some((text, range?))
+}
+
+/// `push_paragraph(result, content)` pushes a paragraph made of `content`,
+/// without its trailing soft break
+fn push_paragraph<'a>(
+    result: &mut Vec<(Event<'a>, Range<usize>)>,
+    mut content: Vec<(Event<'a>, Range<usize>)>,
+) {
+    if content.last().is_some_and(|(e, _)| *e == Event::SoftBreak) {
+        content.pop();
+    }
+    let (Some((_, first)), Some((_, last))) = (content.first(), content.last()) else {
+        return;
+    };
+    let range = first.start..last.end;
+    result.push((Event::Start(Tag::Paragraph), range.clone()));
+    result.extend(content);
+    result.push((Event::End(TagEnd::Paragraph), range));
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+/// the emoji shortcodes supported by default, like on github
+const DEFAULT_EMOJIS: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("angry", "😠"),
+    ("apple", "🍎"),
+    ("arrow_down", "⬇️"),
+    ("arrow_left", "⬅️"),
+    ("arrow_right", "➡️"),
+    ("arrow_up", "⬆️"),
+    ("beer", "🍺"),
+    ("bell", "🔔"),
+    ("blush", "😊"),
+    ("book", "📖"),
+    ("books", "📚"),
+    ("boom", "💥"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("cake", "🍰"),
+    ("calendar", "📆"),
+    ("cat", "🐱"),
+    ("check", "✔️"),
+    ("clap", "👏"),
+    ("clipboard", "📋"),
+    ("coffee", "☕"),
+    ("confused", "😕"),
+    ("construction", "🚧"),
+    ("cool", "🆒"),
+    ("crab", "🦀"),
+    ("cry", "😢"),
+    ("dog", "🐶"),
+    ("email", "📧"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("gear", "⚙️"),
+    ("ghost", "👻"),
+    ("gift", "🎁"),
+    ("grin", "😁"),
+    ("grinning", "😀"),
+    ("hammer", "🔨"),
+    ("heart", "❤️"),
+    ("heavy_check_mark", "✔️"),
+    ("hourglass", "⌛"),
+    ("information_source", "ℹ️"),
+    ("joy", "😂"),
+    ("key", "🔑"),
+    ("laughing", "😆"),
+    ("link", "🔗"),
+    ("lock", "🔒"),
+    ("mag", "🔍"),
+    ("memo", "📝"),
+    ("moon", "🌙"),
+    ("muscle", "💪"),
+    ("no_entry", "⛔"),
+    ("ok", "🆗"),
+    ("ok_hand", "👌"),
+    ("package", "📦"),
+    ("partying_face", "🥳"),
+    ("pencil", "📝"),
+    ("pencil2", "✏️"),
+    ("point_down", "👇"),
+    ("point_left", "👈"),
+    ("point_right", "👉"),
+    ("point_up", "☝️"),
+    ("pray", "🙏"),
+    ("question", "❓"),
+    ("raised_hands", "🙌"),
+    ("recycle", "♻️"),
+    ("rocket", "🚀"),
+    ("rofl", "🤣"),
+    ("rotating_light", "🚨"),
+    ("sad", "😞"),
+    ("scream", "😱"),
+    ("see_no_evil", "🙈"),
+    ("shrug", "🤷"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("sunny", "☀️"),
+    ("sunglasses", "😎"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("trophy", "🏆"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("zap", "⚡"),
+];
+
+/// `emoji_map(custom)` gives the default emoji shortcodes,
+/// overridden and extended by the shortcodes of `custom`
+pub fn emoji_map(custom: HashMap<String, String>) -> HashMap<String, String> {
+    let mut map: HashMap<String, String> = DEFAULT_EMOJIS
+        .iter()
+        .map(|(name, emoji)| (name.to_string(), emoji.to_string()))
+        .collect();
+    map.extend(custom);
+    map
+}
+
+/// `replace_shortcodes(text, emojis)` replaces the shortcodes like `:tada:` in `text`
+/// by their emoji from `emojis`. Unknown shortcodes, like in `10:30:00`, are left alone
+pub fn replace_shortcodes(text: &str, emojis: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(':') {
+        let after = &rest[start + 1..];
+        let name_len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "_+-".contains(c)))
+            .unwrap_or(after.len());
+        let emoji = match after[name_len..].starts_with(':') {
+            true => emojis.get(&after[..name_len]),
+            false => None,
+        };
+        match emoji {
+            Some(emoji) => {
+                result.push_str(&rest[..start]);
+                result.push_str(emoji);
+                rest = &after[name_len + 1..];
+            }
+            // the closing colon can start the next shortcode
+            None => {
+                result.push_str(&rest[..start + 1]);
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
@@ -0,0 +1,143 @@
+use leptos::*;
+
+/// a piece of the text of a paragraph, after the inline extensions
+/// that pulldown_cmark does not support were applied
+pub enum InlineSpan {
+    /// normal text
+    Text(String),
+
+    /// text written like `x^2^`
+    Superscript(String),
+
+    /// text written like `H~2~O`
+    Subscript(String),
+
+    /// text written like `==highlighted==`
+    Mark(String),
+}
+
+impl IntoView for InlineSpan {
+    fn into_view(self) -> View {
+        match self {
+            InlineSpan::Text(s) => s.into_view(),
+            InlineSpan::Superscript(s) => view! { <sup>{s}</sup> }.into_view(),
+            InlineSpan::Subscript(s) => view! { <sub>{s}</sub> }.into_view(),
+            InlineSpan::Mark(s) => view! { <mark>{s}</mark> }.into_view(),
+        }
+    }
+}
+
+/// `split_delimited(spans, delimiter, wrap)` finds the text between two `delimiter`s
+/// in the text spans, and wraps it with `wrap`.
+/// The delimited text can not contain whitespace,
+/// and a delimiter repeated twice is not a delimiter (so `~~strike~~` is left alone).
+pub fn split_delimited(
+    spans: Vec<InlineSpan>,
+    delimiter: char,
+    wrap: fn(String) -> InlineSpan,
+) -> Vec<InlineSpan> {
+    let mut result = Vec::new();
+    for span in spans {
+        match span {
+            InlineSpan::Text(text) => split_text(&text, delimiter, wrap, &mut result),
+            other => result.push(other),
+        }
+    }
+    result
+}
+
+fn split_text(
+    text: &str,
+    delimiter: char,
+    wrap: fn(String) -> InlineSpan,
+    result: &mut Vec<InlineSpan>,
+) {
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(start) = find_single(rest, delimiter) {
+        let after = &rest[start + delimiter.len_utf8()..];
+        match find_single(after, delimiter) {
+            Some(end) if end > 0 && !after[..end].contains(char::is_whitespace) => {
+                plain.push_str(&rest[..start]);
+                if !plain.is_empty() {
+                    result.push(InlineSpan::Text(std::mem::take(&mut plain)));
+                }
+                result.push(wrap(after[..end].to_string()));
+                rest = &after[end + delimiter.len_utf8()..];
+            }
+            _ => {
+                plain.push_str(&rest[..start + delimiter.len_utf8()]);
+                rest = after;
+            }
+        }
+    }
+    plain.push_str(rest);
+    if !plain.is_empty() {
+        result.push(InlineSpan::Text(plain));
+    }
+}
+
+/// `find_single(text, delimiter)` returns the position of the first `delimiter`
+/// in `text` that is not directly preceded or followed by another `delimiter`
+fn find_single(text: &str, delimiter: char) -> Option<usize> {
+    text.char_indices()
+        .find(|&(i, c)| {
+            c == delimiter
+                && !text[..i].ends_with(delimiter)
+                && !text[i + c.len_utf8()..].starts_with(delimiter)
+        })
+        .map(|(i, _)| i)
+}
+
+/// `split_marks(spans)` finds the text between two `==` in the text spans,
+/// like `==highlighted==`, and wraps it in a [`InlineSpan::Mark`].
+/// The marked text can contain spaces, but can not start or end with one,
+/// so comparisons like `a == b` are left alone, and neither is `===`
+pub fn split_marks(spans: Vec<InlineSpan>) -> Vec<InlineSpan> {
+    let mut result = Vec::new();
+    for span in spans {
+        let text = match span {
+            InlineSpan::Text(text) => text,
+            other => {
+                result.push(other);
+                continue;
+            }
+        };
+        let mut plain = String::new();
+        let mut rest = text.as_str();
+        while let Some(start) = find_double_equal(rest) {
+            let after = &rest[start + 2..];
+            let end = find_double_equal(after).filter(|&end| {
+                let marked = &after[..end];
+                !marked.is_empty() && marked.trim() == marked
+            });
+            match end {
+                Some(end) => {
+                    plain.push_str(&rest[..start]);
+                    if !plain.is_empty() {
+                        result.push(InlineSpan::Text(std::mem::take(&mut plain)));
+                    }
+                    result.push(InlineSpan::Mark(after[..end].to_string()));
+                    rest = &after[end + 2..];
+                }
+                None => {
+                    plain.push_str(&rest[..start + 2]);
+                    rest = after;
+                }
+            }
+        }
+        plain.push_str(rest);
+        if !plain.is_empty() {
+            result.push(InlineSpan::Text(plain));
+        }
+    }
+    result
+}
+
+/// `find_double_equal(text)` returns the position of the first `==` in `text`
+/// that is not part of a longer run of `=`
+fn find_double_equal(text: &str) -> Option<usize> {
+    text.match_indices("==")
+        .find(|&(i, _)| !text[..i].ends_with('=') && !text[i + 2..].starts_with('='))
+        .map(|(i, _)| i)
+}
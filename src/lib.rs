@@ -1,18 +1,41 @@
 use leptos::*;
 
 mod render;
-use render::{Renderer, RenderContext};
+pub use render::{render_footnotes, theme_css, RenderContext, RenderContextBuilder, Renderer};
 
 pub use render::HtmlError;
 
 use web_sys::MouseEvent;
 
-use pulldown_cmark_wikilink::{ParserOffsetIter, Options, LinkType, Event};
+use pulldown_cmark_wikilink::{ParserOffsetIter, Options, LinkType, Event, HeadingLevel, Tag, CodeBlockKind};
 
 mod utils;
-use utils::{Callback, HtmlCallback};
+use utils::{
+    block_key, convert_math_delimiters, debounce, merge_text, mermaid_run, parse_info_string,
+};
+pub use utils::{Callback, EventTransform, HtmlCallback, OptionalHtmlCallback};
+
+mod text;
+pub use text::{extract_plain_text, DocStats};
+use text::{doc_stats, footnote_labels, footnote_texts};
+
+mod inline;
+
+mod emoji;
+use emoji::emoji_map;
+
+mod container;
+use container::split_markers;
 
 use core::ops::Range;
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+use std::rc::Rc;
+use std::time::Duration;
+
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::LoadingError;
 
 /// the description of a link, used to render it with a custom callback.
 /// See [pulldown_cmark::Tag::Link] for documentation
@@ -23,6 +46,10 @@ pub struct LinkDescription {
     /// the html view of the element under the link
     pub content: View,
 
+    /// the content of the link as plain text.
+    /// For images, this is the alternative text
+    pub text: String,
+
     /// the title of the link. 
     /// If you don't know what it is, don't worry: it is ofter empty
     pub title: String,
@@ -30,8 +57,81 @@ pub struct LinkDescription {
     /// the type of link
     pub link_type: LinkType,
 
+    /// wether the link is a wikilink to a page that does not exist,
+    /// according to the `wikilink_exists` callback
+    pub broken: bool,
+
     /// wether the link is an image
     pub image: bool,
+
+    /// the size of the image, written like `![alt](<img.png =200x100>)`
+    /// or `![alt](img.png "title =200x100")`
+    pub size: ImageSize,
+}
+
+/// the size of an image, from a trailing `=WIDTHxHEIGHT` in its url or title.
+/// Both sizes are optional, like in `=200` or `=x100`,
+/// and they are in pixels unless they have a unit, like `50%`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ImageSize {
+    pub width: Option<String>,
+    pub height: Option<String>,
+}
+
+/// a click on a link, used to intercept the navigation,
+/// for example to route internal links on the client side
+#[derive(Clone, Debug)]
+pub struct LinkClickEvent {
+    /// the original mouse event. Call `prevent_default` on it to cancel the navigation
+    pub mouse_event: MouseEvent,
+
+    /// the url of the link
+    pub url: String,
+
+    /// wether the link points to the current site:
+    /// relative urls, and absolute urls with the same host as the page
+    pub internal: bool,
+
+    /// wether the link is a wikilink to a page that does not exist
+    pub broken: bool,
+}
+
+/// a click on an image, for example to open it in a lightbox
+#[derive(Clone, Debug)]
+pub struct ImageClickEvent {
+    /// the original mouse event
+    pub mouse_event: MouseEvent,
+
+    /// the url of the image
+    pub url: String,
+
+    /// the alternative text of the image
+    pub alt: String,
+}
+
+/// the description of a code block, used to render it with a custom callback.
+pub struct CodeBlockInfo {
+    /// the language of the code block, as written after the opening fence.
+    /// It is empty if there is none
+    pub lang: String,
+
+    /// the raw content of the code block
+    pub content: String,
+}
+
+/// the description of a heading, used to render it with a custom callback.
+pub struct HeadingInfo {
+    /// the level of the heading, after `heading_offset` was applied
+    pub level: HeadingLevel,
+
+    /// the `id` of the heading, the same as in the table of contents
+    pub id: String,
+
+    /// the content of the heading as plain text
+    pub text: String,
+
+    /// the html view of the content of the heading
+    pub content: View,
 }
 
 #[derive(Clone, Debug)]
@@ -42,13 +142,224 @@ pub struct MarkdownMouseEvent {
     /// the corresponding range in the markdown source, as a slice of [`u8`][u8]
     pub position: Range<usize>,
 
-    // TODO: add a clonable tag for the type of the element
-    // pub tag: pulldown_cmark::Tag<'a>,
+    /// the kind of the element that was clicked on
+    pub tag: MarkdownTag,
+
+    /// the elements containing the clicked element, from the outermost to the innermost.
+    /// For example, clicking on the text of a link in a heading gives
+    /// `[Heading(H2), Link { .. }]`
+    pub ancestors: Vec<MarkdownTag>,
+}
+
+impl MarkdownMouseEvent {
+    /// the innermost element containing the clicked element that matches `f`, if any
+    pub fn find_ancestor(&self, f: impl Fn(&MarkdownTag) -> bool) -> Option<&MarkdownTag> {
+        self.ancestors.iter().rev().find(|x| f(x))
+    }
+}
+
+/// the kind of a markdown element, without its content.
+/// It mirrors [pulldown_cmark::Tag] and the leaf events of [pulldown_cmark::Event],
+/// but owns its data so it can be cloned and stored freely
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarkdownTag {
+    Paragraph,
+    Heading(HeadingLevel),
+    BlockQuote,
+    /// a code block, with its language (empty if there is none)
+    CodeBlock(String),
+    /// a list, with the number of its first item if it is ordered
+    List(Option<u64>),
+    Item,
+    FootnoteDefinition(String),
+    Table,
+    TableHead,
+    TableRow,
+    TableCell,
+    Emphasis,
+    Strong,
+    Strikethrough,
+    Link { url: String, title: String },
+    Image { url: String, title: String },
+    MetadataBlock,
+    Text,
+    Code,
+    Html,
+    Rule,
+    /// a task list checkbox.
+    /// `checked` is its state in the source, so a click asks to set it to `!checked`,
+    /// and `index` is the number of the task in the document, starting from 0
+    TaskListMarker { checked: bool, index: usize },
+    Math,
+    /// an element added by a newer version of the parser, that is not supported yet
+    Unknown,
+}
+
+impl From<&Tag<'_>> for MarkdownTag {
+    fn from(tag: &Tag<'_>) -> Self {
+        match tag {
+            Tag::Paragraph => MarkdownTag::Paragraph,
+            Tag::Heading { level, .. } => MarkdownTag::Heading(*level),
+            Tag::BlockQuote => MarkdownTag::BlockQuote,
+            Tag::CodeBlock(CodeBlockKind::Fenced(info)) => {
+                MarkdownTag::CodeBlock(parse_info_string(info).lang)
+            }
+            Tag::CodeBlock(CodeBlockKind::Indented) => MarkdownTag::CodeBlock(String::new()),
+            Tag::List(start) => MarkdownTag::List(*start),
+            Tag::Item => MarkdownTag::Item,
+            Tag::FootnoteDefinition(label) => MarkdownTag::FootnoteDefinition(label.to_string()),
+            Tag::Table(_) => MarkdownTag::Table,
+            Tag::TableHead => MarkdownTag::TableHead,
+            Tag::TableRow => MarkdownTag::TableRow,
+            Tag::TableCell => MarkdownTag::TableCell,
+            Tag::Emphasis => MarkdownTag::Emphasis,
+            Tag::Strong => MarkdownTag::Strong,
+            Tag::Strikethrough => MarkdownTag::Strikethrough,
+            Tag::Link { dest_url, title, .. } => MarkdownTag::Link {
+                url: dest_url.to_string(),
+                title: title.to_string(),
+            },
+            Tag::Image { dest_url, title, .. } => MarkdownTag::Image {
+                url: dest_url.to_string(),
+                title: title.to_string(),
+            },
+            Tag::MetadataBlock(_) => MarkdownTag::MetadataBlock,
+            #[allow(unreachable_patterns)]
+            _ => MarkdownTag::Unknown,
+        }
+    }
+}
+
+/// a heading of the document, used to build a table of contents
+#[derive(Clone, Debug)]
+pub struct TocEntry {
+    /// the level of the heading
+    pub level: HeadingLevel,
+
+    /// the `id` of the heading, the same as the one rendered when `heading_ids` is enabled
+    pub id: String,
+
+    /// the content of the heading, as plain text
+    pub text: String,
+}
+
+/// a kind of html element rendered from markdown, used to customize its rendering
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ElementKind {
+    Paragraph,
+    Heading,
+    BlockQuote,
+    /// ordered and unordered lists
+    List,
+    ListItem,
+    Table,
+    /// inline code
+    Code,
+    CodeBlock,
+    Link,
+    Image,
+}
+
+/// the frontmatter of a document, like
+/// ```markdown
+/// ---
+/// title: My document
+/// ---
+/// ```
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    /// the format of the frontmatter
+    pub kind: MetadataKind,
+
+    /// the raw content of the frontmatter, without the fences
+    pub content: String,
+}
+
+/// the format of a frontmatter, given by its fences
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetadataKind {
+    /// yaml, fenced with `---`
+    Yaml,
+
+    /// toml, fenced with `+++`
+    Toml,
+}
+
+/// a flavor of markdown, to enable a curated set of features
+/// instead of everything with [`Options::all()`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarkdownPreset {
+    /// plain CommonMark, without any extension
+    CommonMark,
+
+    /// GitHub flavored markdown: tables, strikethrough, task lists, footnotes and autolinks
+    Gfm,
+
+    /// notes like in Obsidian: GFM, with wikilinks, math and a yaml frontmatter
+    Obsidian,
+}
+
+impl MarkdownPreset {
+    /// the parsing options of the preset
+    pub fn options(self) -> Options {
+        let gfm = Options::ENABLE_TABLES
+            | Options::ENABLE_STRIKETHROUGH
+            | Options::ENABLE_TASKLISTS
+            | Options::ENABLE_FOOTNOTES;
+        match self {
+            MarkdownPreset::CommonMark => Options::empty(),
+            MarkdownPreset::Gfm => gfm,
+            MarkdownPreset::Obsidian => {
+                gfm | Options::ENABLE_MATH | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+            }
+        }
+    }
+
+    /// wether the preset turns bare urls into links
+    pub fn autolinks(self) -> bool {
+        matches!(self, MarkdownPreset::Gfm | MarkdownPreset::Obsidian)
+    }
+
+    /// wether the preset parses wikilinks
+    pub fn wikilinks(self) -> bool {
+        matches!(self, MarkdownPreset::Obsidian)
+    }
+}
+
+/// the theme used for syntax highlighting
+#[derive(Clone)]
+pub enum ThemeSource {
+    /// the name of one of the default themes of [syntect::highlighting::ThemeSet]
+    Named(String),
+
+    /// a custom theme
+    Custom(Theme),
+}
+
+impl ThemeSource {
+    /// load a custom theme from the content of a `.tmTheme` file
+    pub fn from_tm_theme(bytes: &[u8]) -> Result<Self, LoadingError> {
+        let theme = ThemeSet::load_from_reader(&mut Cursor::new(bytes))?;
+        Ok(ThemeSource::Custom(theme))
+    }
+}
+
+/// a syntax highlighter for code blocks, to use instead of the built-in syntect one,
+/// for example a lighter one or one based on tree-sitter
+pub trait Highlighter {
+    /// `highlight(lang, code)` returns the html of the highlighted `code`,
+    /// written in the language `lang` (empty if it is unknown).
+    /// It is inserted as it is, so it must be escaped.
+    /// Returning `None` renders `code` without highlighting
+    fn highlight(&self, lang: &str, code: &str) -> Option<String>;
 }
 
 #[cfg(feature="debug")]
 pub mod debug {
     use super::*;
+    /// provided as a context with `provide_context`, it receives the events rendered by the
+    /// [`Markdown`] components, each with its range in the source, like `Text(..) 0..5`.
+    /// They are the events given to the renderer, after `transform_events`
     #[derive(Copy, Clone)]
     pub struct EventInfo(pub WriteSignal<Vec<String>>);
 }
@@ -58,7 +369,7 @@ pub mod debug {
 pub fn Markdown(
     /// the markdown text to render
     #[prop(into)]
-    src: String,
+    src: MaybeSignal<String>,
 
     /// the callback called when a component is clicked.
     /// if you want to controll what happens when a link is clicked,
@@ -70,51 +381,802 @@ pub fn Markdown(
     #[prop(optional, into)] 
     render_links: Option<HtmlCallback<LinkDescription>>,
 
+    /// wether to write the url and the text of email links as html entities,
+    /// to hide them from the scrapers reading the html sent by the server
+    #[prop(optional)]
+    obfuscate_emails: bool,
+
+    /// wether to open external links in a new tab,
+    /// with `target="_blank"` and `rel="noopener noreferrer"`.
+    /// Relative links and anchors always stay in the same tab
+    #[prop(optional)]
+    external_links_new_tab: bool,
+
+    /// the host of the site, like `example.com`.
+    /// Absolute links to this host are not considered external by `external_links_new_tab`
+    #[prop(optional, into)]
+    site_host: Option<String>,
+
+    /// the url against which the relative urls of links and images are resolved,
+    /// for documents whose assets live under a known prefix: with `https://example.com/docs/`,
+    /// `./img/logo.png` becomes `https://example.com/docs/img/logo.png`.
+    /// Absolute urls and anchors are kept as they are
+    #[prop(optional, into)]
+    base_url: Option<String>,
+
+    /// the `rel` attribute of external links, like `nofollow ugc` for user content.
+    /// Links to `site_host` and relative links do not get it
+    #[prop(optional, into)]
+    link_rel: Option<String>,
+
+    /// the callback called when a link is clicked, before the navigation happens.
+    /// Links rendered by `render_links` are not concerned
+    #[prop(optional, into)]
+    on_link_click: Option<Callback<LinkClickEvent>>,
+
+    /// the callback called when an image is clicked.
+    /// Images rendered by `render_links` are not concerned
+    #[prop(optional, into)]
+    on_image_click: Option<Callback<ImageClickEvent>>,
+
+    /// the callback called with the frontmatter of the document, if it has one.
+    /// The frontmatter is not rendered
+    #[prop(optional, into)]
+    on_metadata: Option<Callback<Metadata>>,
+
+    /// the callback called with each error that happens while rendering,
+    /// and with each footnote that is defined but never referenced
+    #[prop(optional, into)]
+    on_error: Option<Callback<HtmlError>>,
+
+    /// the callback used to render code blocks.
+    /// When it returns `None`, the code block is rendered with syntax highlighting,
+    /// so it can be used to only customize certain languages
+    #[prop(optional, into)]
+    render_code_block: Option<OptionalHtmlCallback<CodeBlockInfo>>,
+
+    /// the callback used to render headings, for example to add a copy link button.
+    /// It replaces the default rendering, including `heading_anchors`
+    #[prop(optional, into)]
+    render_heading: Option<HtmlCallback<HeadingInfo>>,
+
     /// the name of the theme used for syntax highlighting.
-    /// Only the default themes of [syntect::Theme] are supported
-    #[prop(optional)] 
-    theme: Option<String>,
+    /// Only the default themes of [syntect::Theme] are supported.
+    /// When it is a signal, the document is highlighted again each time it changes.
+    /// For a cheaper switch, see `css_classes`
+    #[prop(into, default=None.into())]
+    theme: MaybeSignal<Option<String>>,
+
+    /// the theme used for syntax highlighting, either one of the defaults or a custom one.
+    /// Takes precedence over `theme`
+    #[prop(optional, into)]
+    theme_source: Option<ThemeSource>,
+
+    /// the syntaxes used for syntax highlighting, instead of the defaults of syntect.
+    /// To add syntaxes to the defaults, extend them with
+    /// `SyntaxSet::load_defaults_newlines().into_builder()`.
+    /// Code blocks in an unknown language are rendered without highlighting,
+    /// with a `language-{lang}` class for client side highlighters
+    #[prop(optional)]
+    syntax_set: Option<SyntaxSet>,
+
+    /// the themes used instead of `theme` for some languages,
+    /// like `HashMap::from([("diff".to_string(), diff_theme)])`.
+    /// They are syntect themes, for example loaded with [`ThemeSet::load_from_reader`].
+    /// They do not apply with `css_classes`, where the stylesheet gives the colors
+    #[prop(optional)]
+    theme_by_language: HashMap<String, Theme>,
+
+    /// the highlighter used instead of syntect, like `Rc::new(MyHighlighter)`.
+    /// The highlighting props (`theme`, `syntax_set`, `css_classes`, ...) do not apply to it
+    #[prop(optional)]
+    highlighter: Option<Rc<dyn Highlighter>>,
+
+    /// wether to highlight code with css classes like `source rust keyword`
+    /// instead of inline styles. The stylesheet of a theme is given by [`theme_css`],
+    /// so themes can be switched with css only
+    #[prop(optional)]
+    css_classes: bool,
+
+    /// wether to add a button to copy the source of each code block
+    #[prop(optional)]
+    code_block_copy_button: bool,
+
+    /// wether to show line numbers in front of each line of highlighted code blocks
+    #[prop(optional)]
+    code_block_line_numbers: bool,
+
+    /// the language of indented code blocks, and of fenced ones without a language,
+    /// for example `text` or `rust`. Without it, they are not highlighted
+    #[prop(optional, into)]
+    default_code_language: Option<String>,
+
+    /// the size of the biggest code block that is highlighted, in bytes.
+    /// Bigger code blocks are rendered like code in an unknown language,
+    /// so a huge block pasted in an editor does not freeze the page.
+    /// There is no limit by default
+    #[prop(optional)]
+    max_highlight_bytes: Option<usize>,
+
+    /// the languages of the code blocks rendered as diagrams.
+    /// They are rendered as `<div class="diagram {lang}">` containing the raw source,
+    /// and `mermaid.run()` is called after the rendering if mermaid is loaded in the page.
+    /// Other libraries can be used by selecting the `diagram` class.
+    #[prop(default=vec!["mermaid".to_string()])]
+    diagram_languages: Vec<String>,
+
+    /// the prefix of the classes of alerts.
+    /// A blockquote starting with a line like `[!NOTE]` is rendered as an alert,
+    /// like on github: `<blockquote class="alert alert-note">` with a title.
+    /// The supported kinds are `NOTE`, `TIP`, `IMPORTANT`, `WARNING` and `CAUTION`
+    #[prop(optional, into)]
+    alert_class_prefix: Option<String>,
+
+    /// the classes added to each kind of element, for example
+    /// `HashMap::from([(ElementKind::Table, "table table-striped".to_string())])`.
+    /// They are added after the classes the elements already have
+    #[prop(optional)]
+    class_map: HashMap<ElementKind, String>,
+
+    /// the kinds of elements that are not rendered at all, with their content,
+    /// for example `HashSet::from([ElementKind::Image])` for a preview without images
+    #[prop(optional)]
+    disabled_elements: HashSet<ElementKind>,
+
+    /// wether to replace shortcodes like `:tada:` by their emoji.
+    /// Unknown shortcodes are left as is, and code is never affected
+    #[prop(optional)]
+    emoji_shortcodes: bool,
+
+    /// custom emoji shortcodes, like `("ferris", "🦀")`,
+    /// added to the default ones (and replacing them if they have the same name)
+    #[prop(optional)]
+    emojis: HashMap<String, String>,
+
+    /// wether to render containers written like
+    /// ```markdown
+    /// :::note Optional title
+    /// the content, with any **markdown**
+    /// :::
+    /// ```
+    /// as `<div class="note"><p class="note-title">Optional title</p>...</div>`.
+    /// `:::details Title` is rendered as a collapsible `<details>` section,
+    /// which is open by default with `:::details-open`.
+    /// Containers can be nested
+    #[prop(optional)]
+    containers: bool,
+
+    /// the names of the containers that can be used, like `vec!["note", "details"]`.
+    /// Other containers are rendered as normal text. All the names are allowed by default
+    #[prop(optional, into)]
+    container_names: Option<Vec<String>>,
+
+    /// wether to render `x^2^` as a superscript and `H~2~O` as a subscript.
+    /// The text between the delimiters can not contain spaces,
+    /// doubled delimiters are left alone (so `~~strike~~` still works),
+    /// and a delimiter can be escaped with a backslash: `\^`.
+    /// When this is enabled, single tildes no longer make a strikethrough
+    #[prop(optional)]
+    sub_superscript: bool,
+
+    /// wether to render `==highlighted==` text in a `<mark>`.
+    /// The highlighted text can not start or end with a space, so `a == b` is left alone.
+    /// It can not contain other inline elements, like emphasis or code
+    #[prop(optional)]
+    highlight_syntax: bool,
+
+    /// wether to also parse math written with the latex delimiters
+    /// `\(inline\)` and `\[display\]`, by converting them to `$` and `$$` before parsing.
+    /// Delimiters in code and escaped backslashes are left alone,
+    /// and the positions in the source are still the ones of `src`
+    #[prop(optional)]
+    latex_delimiters: bool,
+
+    /// wether the checkboxes of task lists are disabled, so they can not be toggled.
+    /// The click callback is still called when they are clicked
+    #[prop(optional)]
+    read_only_tasks: bool,
+
+    /// wether to render errors as an empty `<span class="error">`
+    /// instead of showing their message in a red box
+    #[prop(optional)]
+    hide_errors: bool,
+
+    /// wether to add the position of block elements in the source, as
+    /// `data-source-start` and `data-source-end` attributes with byte offsets.
+    /// Useful to synchronize an editor with the rendered document
+    #[prop(optional)]
+    source_pos: bool,
+
+    /// wether to load images immediately.
+    /// By default, images are loaded lazily with `loading="lazy"` and `decoding="async"`
+    #[prop(optional)]
+    eager_images: bool,
+
+    /// wether to sanitize raw html before rendering it, and to remove the urls
+    /// of links that run scripts (`javascript:`, `vbscript:`, `data:`).
+    /// Only disable it if you trust the markdown source !
+    #[prop(default=true)]
+    sanitize_html: bool,
+
+    /// wether to align table cells with the classes `md-align-left`, `md-align-center`
+    /// and `md-align-right` instead of an inline `text-align` style,
+    /// so the alignment can be overridden with css
+    #[prop(optional)]
+    alignment_classes: bool,
+
+    /// wether to wrap tables in a `<div class="table-wrapper">` that scrolls horizontally,
+    /// so wide tables do not overflow on small screens
+    #[prop(optional)]
+    responsive_tables: bool,
+
+    /// wether to give unordered lists a class from the bullet character they are written with,
+    /// for editors that show them differently: `list-disc` for `-`, `list-circle` for `*`
+    /// and `list-square` for `+`. CommonMark treats the three bullets the same
+    #[prop(optional)]
+    bullet_classes: bool,
+
+    /// wether to render emphasis, strong text and strikethrough with the presentational
+    /// `<i>`, `<b>` and `<s>` tags, like before, instead of the semantic `<em>`, `<strong>`
+    /// and `<del>` ones that screen readers announce. Only for stylesheets relying on them
+    #[prop(optional)]
+    presentational_tags: bool,
+
+    /// wether to render the markdown inside a line of text, like a comment or a chat message.
+    /// Paragraphs are rendered as `<span>` instead of `<p>`,
+    /// and the wrapping element is a `<span>` unless `wrapper_tag` is set.
+    /// Containers are not supported in this mode
+    #[prop(optional)]
+    inline: bool,
+
+    /// wether to show the text of a footnote when its reference is hovered,
+    /// with a `title` attribute
+    #[prop(optional)]
+    footnote_tooltips: bool,
+
+    /// wether to add an `id` to each heading, derived from its text content
+    /// like on github: `## My Section` gets `id="my-section"`.
+    /// A heading written with an id, like `## My Section {#custom}`, always gets it
+    #[prop(optional)]
+    heading_ids: bool,
+
+    /// wether to add a permalink at the end of each heading:
+    /// `<a class="heading-anchor" href="#my-section" aria-hidden="true">#</a>`.
+    /// It can be hidden until the heading is hovered with css.
+    /// The headings get an `id` even if `heading_ids` is disabled
+    #[prop(optional)]
+    heading_anchors: bool,
+
+    /// the number of levels the headings are shifted by, to embed the document
+    /// in a page that already has a title: with `1`, `#` is rendered as `<h2>`.
+    /// Headings that would go below `<h6>` stay `<h6>`.
+    /// The levels given to `on_toc` are shifted too
+    #[prop(optional)]
+    heading_offset: u8,
+
+    /// a prefix added before the `id` of headings
+    #[prop(optional, into)]
+    heading_id_prefix: Option<String>,
+
+    /// the callback called with the outline of the document, once it has been rendered.
+    /// Enable `heading_ids` to make the `id`s of the entries usable as anchors
+    #[prop(optional, into)]
+    on_toc: Option<Callback<Vec<TocEntry>>>,
+
+    /// the callback called with the statistics of the document
+    /// (word count, reading time, ...) each time it is rendered
+    #[prop(optional, into)]
+    on_stats: Option<Callback<DocStats>>,
+
+    /// the callback called once the document has been rendered and mounted in the DOM,
+    /// after each change of the source.
+    /// This is the place to post-process the content with third-party libraries,
+    /// for example to measure it or to run KaTeX auto-render
+    #[prop(optional, into)]
+    on_render_complete: Option<Callback<()>>,
+
+    /// the reading speed used to estimate the reading time of `on_stats`
+    #[prop(default = 200)]
+    words_per_minute: u32,
+
+    /// wether to count the words of code blocks in `on_stats`
+    #[prop(optional)]
+    stats_include_code: bool,
 
     /// wether to enable wikilinks support.
-    /// Wikilinks look like [[shortcut link]] or [[url|name]]
-    #[prop(into, default=false.into())]
-    wikilinks: MaybeSignal<bool>,
+    /// Wikilinks look like [[shortcut link]] or [[url|name]].
+    /// When it is not set, `preset` decides (it is disabled by default)
+    #[prop(optional, into)]
+    wikilinks: Option<MaybeSignal<bool>>,
+
+    /// the callback mapping the target of a wikilink to its url,
+    /// for example `Page Name` to `/wiki/page-name`.
+    /// Without it, the target is used as the url
+    #[prop(optional, into)]
+    wikilink_resolver: Option<Callback<String, String>>,
+
+    /// the callback telling if the target of a wikilink exists, for example
+    /// `move |target| pages.contains(&target)`.
+    /// Wikilinks to missing pages get the `wikilink-broken` class
+    #[prop(optional, into)]
+    wikilink_exists: Option<Callback<String, bool>>,
+
+    /// wether to turn bare urls, like `https://example.com` or `www.example.com`,
+    /// and email addresses, like `hello@example.com`, into links.
+    /// They are rendered like other links, with `render_links` if it is set.
+    /// When it is not set, `preset` decides (it is disabled by default)
+    #[prop(optional, into)]
+    autolinks: Option<MaybeSignal<bool>>,
+
+    /// wether to convert straight quotes to curly quotes, `--` to en-dashes
+    /// and `...` to ellipses. Code is never affected.
+    /// When it is not set, `parse_options` decides (it is enabled by default)
+    #[prop(optional, into)]
+    smart_punctuation: Option<MaybeSignal<bool>>,
 
     /// wether to convert soft breaks to hard breaks.
+    /// Soft breaks are the line breaks inside a paragraph: by default they are
+    /// displayed as a space, like in html, and with this prop as a `<br>`
     #[prop(into, default=false.into())]
     hard_line_breaks: MaybeSignal<bool>,
 
-    /// pulldown_cmark options.
+    /// the class of the element wrapping the markdown,
+    /// `markdown-container` by default
+    #[prop(optional, into)]
+    class: Option<String>,
+
+    /// the tag of the element wrapping the markdown, for example `article` or `section`.
+    /// It is a `div` by default, or a `span` with `inline`
+    #[prop(optional, into)]
+    wrapper_tag: Option<String>,
+
+    /// wether to render the markdown directly, without any wrapping element
+    #[prop(optional)]
+    no_wrapper: bool,
+
+    /// when set, the markdown is only rendered again once the source
+    /// did not change for this duration, in milliseconds.
+    /// Useful for live previews of big documents
+    #[prop(optional)]
+    debounce_ms: Option<u32>,
+
+    /// wether to render each top level block of the document as a keyed item,
+    /// so that when the source changes, only the blocks that changed are rendered again.
+    /// Useful for big documents in editors, where an edit changes a single paragraph.
+    ///
+    /// The whole document is still parsed and rendered with a single context, so footnotes,
+    /// heading ids and tasks are numbered like without `keyed_blocks`.
+    /// A block is keyed by the hash of its source, of the reactive props, of the state left
+    /// by the blocks before it (like the footnote numbers), and the number of identical
+    /// blocks before it, so it keeps its DOM when other blocks are added, removed or edited.
+    /// When the positions in the source are used (`on_click`, `source_pos`),
+    /// the key also contains the position of the block, so the blocks after an edit
+    /// are rendered again to get their new positions
+    #[prop(optional)]
+    keyed_blocks: bool,
+
+    /// wether the source is streamed, like the answer of a chat bot written token by token.
+    /// The blocks are rendered like with `keyed_blocks`, so an unfinished block at the end
+    /// (an open code fence, a table being written) does not make the whole view flicker.
+    /// The source is parsed with the same options as without `streaming`.
+    /// A code block whose fence is not closed yet is not highlighted, and gets the
+    /// `code-block-open` class
+    #[prop(optional)]
+    streaming: bool,
+
+    /// when set, only this number of top-level blocks are rendered,
+    /// so a very long document can be shown progressively by raising it on scroll.
+    /// The footnotes of the blocks that are not rendered are not shown
+    #[prop(into, default=None.into())]
+    max_blocks: MaybeSignal<Option<usize>>,
+
+    /// a flavor of markdown, like [`MarkdownPreset::Gfm`], setting the parsing options
+    /// and the default of `wikilinks` and `autolinks`.
+    /// `parse_options`, `wikilinks` and `autolinks` override it when they are set
+    #[prop(optional)]
+    preset: Option<MarkdownPreset>,
+
+    /// pulldown_cmark options, the ones of `preset` or [`Options::all()`] by default.
     /// See [`Options`][pulldown_cmark_wikilink::Options] for reference.
+    ///
+    /// The other props are applied on top of these options, so they compose:
+    /// - the features a prop relies on are always enabled,
+    ///   like task lists for `read_only_tasks` or metadata blocks for `on_metadata`
+    /// - `smart_punctuation`, when set, overrides the smart punctuation flag
+    /// - `wikilinks`, `hard_line_breaks` and `autolinks` do not depend on the options
     #[prop(optional, into)]
     parse_options: Option<pulldown_cmark_wikilink::Options>,
 
+    /// a transformation of the parsed events, run just before rendering.
+    /// It gets the events, each with its byte range in `src`, and returns the events to render,
+    /// so it can add, remove or change any of them to support a custom syntax.
+    /// The built-in transformations (`hard_line_breaks`, `autolinks`, `containers`)
+    /// were already applied. Start and end tags must stay balanced
+    #[prop(optional, into)]
+    transform_events: Option<EventTransform>,
+
+    /// the options of the rendering, built once with [`RenderContext::builder`]
+    /// and reused by several components. When it is set, it is used instead of
+    /// the props setting how the elements are rendered (`on_click`, `render_links`,
+    /// `heading_ids`, `sanitize_html`, ...), while the props about the source and
+    /// the parsing (`parse_options`, `wikilinks`, `theme`, `max_blocks`, ...) still apply
+    #[prop(optional)]
+    context: Option<RenderContextBuilder>,
+
     ) -> impl IntoView 
      {
-    let context = RenderContext::new(
-        theme,
-        on_click,
-        render_links,
-    );
+    let syntax_set = syntax_set.map(Rc::new);
+    let theme_by_language = Rc::new(theme_by_language);
+    let emojis = emoji_shortcodes.then(|| Rc::new(emoji_map(emojis)));
+
+    let src = match debounce_ms {
+        Some(delay) => debounce(src, Duration::from_millis(delay.into())).into(),
+        None => src,
+    };
+
+    let context_builder = match context {
+        Some(context) => context,
+        None => RenderContext::builder()
+            .syntax_set(syntax_set)
+            .theme_by_language(theme_by_language)
+            .highlighter(highlighter)
+            .onclick(on_click)
+            .render_links(render_links)
+            .wikilink_resolver(wikilink_resolver)
+            .wikilink_exists(wikilink_exists)
+            .obfuscate_emails(obfuscate_emails)
+            .external_links_new_tab(external_links_new_tab)
+            .site_host(site_host)
+            .base_url(base_url)
+            .link_rel(link_rel)
+            .on_link_click(on_link_click)
+            .on_image_click(on_image_click)
+            .on_metadata(on_metadata)
+            .on_error(on_error)
+            .render_code_block(render_code_block)
+            .render_heading(render_heading)
+            .copy_button(code_block_copy_button)
+            .css_classes(css_classes)
+            .line_numbers(code_block_line_numbers)
+            .default_code_language(default_code_language)
+            .max_highlight_bytes(max_highlight_bytes)
+            .diagram_languages(diagram_languages)
+            .alert_class_prefix(alert_class_prefix)
+            .class_map(class_map)
+            .disabled_elements(disabled_elements)
+            .emojis(emojis)
+            .containers(containers)
+            .container_names(container_names)
+            .sub_superscript(sub_superscript)
+            .highlight_syntax(highlight_syntax)
+            .read_only_tasks(read_only_tasks)
+            .hide_errors(hide_errors)
+            .source_pos(source_pos)
+            .eager_images(eager_images)
+            .sanitize_html(sanitize_html)
+            .alignment_classes(alignment_classes)
+            .responsive_tables(responsive_tables)
+            .bullet_classes(bullet_classes)
+            .presentational_tags(presentational_tags)
+            .inline(inline)
+            .heading_ids(heading_ids)
+            .heading_anchors(heading_anchors)
+            .heading_offset(heading_offset)
+            .heading_id_prefix(heading_id_prefix),
+    };
+
+    // the props that also change the parsing come from the context,
+    // which may have been given by the `context` prop
+    let with_positions = context_builder.onclick.is_some() || context_builder.source_pos;
+    let read_only_tasks = context_builder.read_only_tasks;
+    let has_metadata_callback = context_builder.on_metadata.is_some();
+    let containers = context_builder.containers;
+    let inline = context_builder.inline;
 
-    let options = parse_options.unwrap_or(Options::all());
+    let keyed = keyed_blocks || streaming;
+    // the number of times the document was rendered, that keys the footnotes
+    let renders = store_value(0u64);
 
-    let mut stream: Vec<_> = ParserOffsetIter::new_ext(src.as_str(), options, wikilinks.get())
-        .collect();
+    // `render(src)` parses and renders the whole markdown `src`, and gives its top level
+    // blocks, each with its key. It runs again each time the source,
+    // or one of the reactive props (`wikilinks`, `hard_line_breaks`, ...) changes
+    let render = move |src: String| {
+        let wikilinks = wikilinks
+            .as_ref()
+            .map_or(preset.is_some_and(MarkdownPreset::wikilinks), |w| w.get());
+        let autolinks = autolinks
+            .as_ref()
+            .map_or(preset.is_some_and(MarkdownPreset::autolinks), |a| a.get());
+        let mut options = parse_options
+            .or(preset.map(MarkdownPreset::options))
+            .unwrap_or(Options::all());
+        if read_only_tasks {
+            options.insert(Options::ENABLE_TASKLISTS);
+        }
+        if has_metadata_callback {
+            options.insert(Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
+            options.insert(Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS);
+        }
+        if let Some(smart_punctuation) = &smart_punctuation {
+            options.set(Options::ENABLE_SMART_PUNCTUATION, smart_punctuation.get());
+        }
+        if latex_delimiters {
+            options.insert(Options::ENABLE_MATH);
+        }
 
-    if hard_line_breaks.get() {
-        for (r, _) in &mut stream {
-            if *r == Event::SoftBreak {
-                *r = Event::HardBreak
+        let converted = latex_delimiters.then(|| convert_math_delimiters(&src));
+        let parsed = converted.as_ref().map_or(src.as_str(), |(converted, _)| converted.as_str());
+        let mut stream: Vec<_> = ParserOffsetIter::new_ext(parsed, options, wikilinks).collect();
+        if let Some((_, offsets)) = &converted {
+            // the events are rendered, and reported, with their positions in `src`
+            for (_, range) in &mut stream {
+                *range = offsets.original(range.start)..offsets.original(range.end);
             }
         }
+
+        let hard_line_breaks = hard_line_breaks.get();
+        if hard_line_breaks {
+            for (r, _) in &mut stream {
+                if *r == Event::SoftBreak {
+                    *r = Event::HardBreak
+                }
+            }
+        }
+
+        if let Some(on_stats) = &on_stats {
+            let events = stream.iter().map(|(event, _)| event);
+            on_stats.call(doc_stats(events, words_per_minute, stats_include_code))
+        }
+
+        if autolinks {
+            stream = merge_text(stream, &src);
+        }
+
+        if containers {
+            stream = split_markers(stream);
+        }
+
+        if let Some(transform_events) = &transform_events {
+            stream = transform_events.call(stream);
+        }
+
+        #[cfg(feature = "debug")]
+        if let Some(debug::EventInfo(info)) = use_context() {
+            let events = stream.iter().map(|(event, range)| format!("{event:?} {range:?}"));
+            info.set(events.collect())
+        }
+
+        let footnote_texts = match footnote_tooltips {
+            true => footnote_texts(stream.iter().map(|(event, _)| event)),
+            false => HashMap::new(),
+        };
+        let (footnotes, references) = footnote_labels(stream.iter().map(|(event, _)| event));
+        if let Some(on_error) = &context_builder.on_error {
+            let mut unused: Vec<_> = footnotes.difference(&references).collect();
+            unused.sort();
+            for label in unused {
+                on_error.call(HtmlError::UnusedFootnote(label.clone()))
+            }
+        }
+        let theme_name = theme.get();
+        let max_blocks = max_blocks.get();
+        // a block is rendered again when one of the reactive props changes
+        let props = (&theme_name, wikilinks, autolinks, hard_line_breaks, options.bits());
+        let mut context_builder = context_builder.clone();
+        // the theme of the `context` prop is kept unless one is given
+        if let Some(theme) = theme_source.clone().or(theme_name.clone().map(ThemeSource::Named)) {
+            context_builder = context_builder.theme(Some(theme));
+        }
+        let context = context_builder
+            .autolinks(autolinks)
+            .wikilinks(wikilinks)
+            .streaming(streaming)
+            .footnote_texts(footnote_texts)
+            .footnote_labels(Some(footnotes))
+            .build();
+
+        let mut stream = stream.into_iter();
+        let mut renderer = Renderer::new(&context, &src, &mut stream);
+        let mut seen = HashMap::new();
+        let mut blocks = match keyed {
+            true => keyed_blocks(
+                &mut renderer,
+                &context,
+                &src,
+                props,
+                with_positions,
+                max_blocks,
+                &mut seen,
+            ),
+            false => {
+                let content = match max_blocks {
+                    Some(n) => renderer.take_blocks(n),
+                    None => renderer.collect_view(),
+                };
+                vec![(0, content)]
+            }
+        };
+        if let Some(footnotes) = render_footnotes(&context) {
+            // the footnotes depend on the whole document, so they get a new key each time
+            renders.update_value(|n| *n += 1);
+            let key = block_key(("footnotes", renders.get_value()), &mut seen);
+            blocks.push((key, footnotes.into_view()));
+        }
+
+        if let Some(on_toc) = &on_toc {
+            on_toc.call(context.take_toc())
+        }
+
+        if context.has_diagrams() {
+            // the diagrams can only be rendered once they are in the DOM
+            create_effect(|_| request_animation_frame(|| {
+                if mermaid_run().is_err() {
+                    log::warn!("diagrams were rendered, but mermaid is not loaded")
+                }
+            }));
+        }
+
+        if let Some(on_render_complete) = on_render_complete.clone() {
+            // like the diagrams, the view is only in the DOM on the next frame
+            create_effect(move |_| {
+                let on_render_complete = on_render_complete.clone();
+                request_animation_frame(move || on_render_complete.call(()))
+            });
+        }
+
+        blocks
+    };
+
+    let content = match keyed {
+        // the blocks that did not change keep their key, and so their DOM, see `block_key`
+        true => view! {
+            <For
+                each=move || render(src.get())
+                key=|(key, _)| *key
+                children=|(_, block)| block
+            />
+        }
+        .into_view(),
+        false => (move || render(src.get()).into_iter().map(|(_, block)| block).collect_view())
+            .into_view(),
+    };
+
+    if no_wrapper {
+        return content;
+    }
+
+    let default_tag = if inline { "span" } else { "div" };
+    let wrapper_tag = wrapper_tag.unwrap_or(default_tag.to_string());
+    let class = class.unwrap_or("markdown-container".to_string());
+
+    html::custom(html::Custom::new(wrapper_tag))
+        .attr("class", class)
+        .child(content)
+        .into_view()
+}
+
+/// `keyed_blocks(renderer, context, src, props, with_positions, max_blocks, seen)`
+/// renders the top-level blocks of `src`, at most `max_blocks`, each with its key.
+/// The key of a block depends on its source, on the reactive `props`, on the state
+/// left by the blocks before it and, `with_positions`, on its position, see [`block_key`]
+fn keyed_blocks<'a, I>(
+    renderer: &mut Renderer<'a, '_, I>,
+    context: &RenderContext,
+    src: &str,
+    props: impl std::hash::Hash + Copy,
+    with_positions: bool,
+    max_blocks: Option<usize>,
+    seen: &mut HashMap<u64, usize>,
+) -> Vec<(u64, View)>
+where
+    I: Iterator<Item = (Event<'a>, Range<usize>)>,
+{
+    let mut blocks = Vec::new();
+    while blocks.len() < max_blocks.unwrap_or(usize::MAX) {
+        // the state left by the previous blocks, like the numbers of the footnotes,
+        // is part of the key, since it changes how the block is rendered
+        let state = context.state_hash();
+        let Some((range, html)) = renderer.next_block() else {
+            break;
+        };
+        let block = src.get(range.clone()).unwrap_or_default();
+        let position = with_positions.then_some(range.start);
+        let key = block_key((props, block, state, position), seen);
+        blocks.push((key, html.into_view()));
     }
-    view! {
-        <>
-            <div class="markdown-container"> 
-                {Renderer::new(&context, &mut stream.into_iter()).collect_view()}
-            </div>
-        </>
+    blocks
+}
+
+/// `render_to_string(src, options)` renders the markdown `src` to an html string,
+/// for server side rendering or static site generation.
+/// The html is the same as the one of the [`Markdown`] component with the default props,
+/// but without interactivity: click callbacks only exist in the browser.
+/// The browser apis used by the component (clipboard, diagrams, debounce) are only
+/// called from event handlers and effects, so they never run on the server.
+/// Only available with the `ssr` feature
+#[cfg(feature = "ssr")]
+pub fn render_to_string(src: &str, options: Options) -> String {
+    let runtime = create_runtime();
+    let html = view! {
+        <Markdown src=src.to_string() parse_options=options/>
+    }
+    .into_view()
+    .render_to_string()
+    .to_string();
+    runtime.dispose();
+    html
+}
+
+/// `markdown_to_html(src, options, context, pretty)` renders the markdown `src`,
+/// parsed with `options`, to an html string without the wrapping element,
+/// for example to cache it or to generate a static site.
+/// It is rendered like the [`Markdown`] component given the `context` prop,
+/// see [`RenderContextBuilder`] for the available options.
+/// Unlike [`render_to_string`], it fails with the first error met while rendering,
+/// instead of rendering the error in a red box.
+/// The html is minified, unless `pretty` is set: then each block element is on its own,
+/// indented line, which makes generated pages readable and diff-friendly.
+///
+/// The context is a builder, since a [`RenderContext`] holds the state of a single render.
+/// Only available with the `ssr` feature, that renders leptos views to strings
+#[cfg(feature = "ssr")]
+pub fn markdown_to_html(
+    src: &str,
+    options: Options,
+    context: RenderContextBuilder,
+    pretty: bool,
+) -> Result<String, HtmlError> {
+    let runtime = create_runtime();
+    let error = Rc::new(std::cell::RefCell::new(None));
+    let on_error = {
+        let error = error.clone();
+        let on_error = context.on_error.clone();
+        move |e: HtmlError| {
+            if let Some(on_error) = &on_error {
+                on_error.call(e.clone())
+            }
+            error.borrow_mut().get_or_insert(e);
+        }
+    };
+    let context = context.on_error(Some(Callback::new(on_error)));
+    let html = view! {
+        <Markdown src=src.to_string() parse_options=options context=context no_wrapper=true/>
+    }
+    .into_view()
+    .render_to_string()
+    .to_string();
+    runtime.dispose();
+    match (error.take(), pretty) {
+        (Some(e), _) => Err(e),
+        (None, true) => Ok(utils::pretty_html(&html)),
+        (None, false) => Ok(html),
     }
 }
 
+#[cfg(all(test, feature = "ssr"))]
+mod tests {
+    use super::*;
+
+    /// `keys(src)` gives the keys of the blocks of `src`, rendered with the default context
+    fn keys(src: &str) -> Vec<u64> {
+        let runtime = create_runtime();
+        let context = RenderContext::builder().build();
+        let mut stream = ParserOffsetIter::new_ext(src, Options::all(), false);
+        let mut renderer = Renderer::new(&context, src, &mut stream);
+        let mut seen = HashMap::new();
+        let blocks = keyed_blocks(&mut renderer, &context, src, (), false, None, &mut seen);
+        let keys = blocks.into_iter().map(|(key, _)| key).collect();
+        runtime.dispose();
+        keys
+    }
+
+    #[test]
+    fn unchanged_blocks_keep_their_key_after_an_edit() {
+        let before = keys("# Title\n\nfirst\n\nsecond\n");
+        let after = keys("# Title\n\nfirst, edited\n\nsecond\n");
+        assert_eq!(before.len(), 3);
+        assert_eq!(before[0], after[0]);
+        assert_ne!(before[1], after[1]);
+        assert_eq!(before[2], after[2]);
+    }
+}
@@ -2,30 +2,64 @@ use leptos::html::AnyElement;
 use leptos::*;
 
 use core::ops::Range;
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use std::time::Duration;
 
-use syntect::highlighting::{Theme, ThemeSet};
-use syntect::parsing::SyntaxSet;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Color, Theme, ThemeSet};
+use syntect::html::{
+    css_for_theme_with_class_style, styled_line_to_highlighted_html, ClassStyle,
+    ClassedHTMLGenerator, IncludeBackground,
+};
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
 
 use web_sys::MouseEvent;
 
 use pulldown_cmark_wikilink::{
-    Alignment, CodeBlockKind, Event, HeadingLevel, Tag, TagEnd,
+    Alignment, CodeBlockKind, Event, HeadingLevel, LinkType, MetadataBlockKind, Tag, TagEnd,
 };
 
-use super::{LinkDescription, MarkdownMouseEvent};
-use crate::utils::{as_closing_tag, Callback, HtmlCallback};
+use crate::container::{marker_text, parse_marker, Marker};
+use crate::emoji::replace_shortcodes;
+use crate::inline::{split_delimited, split_marks, InlineSpan};
+
+use super::{
+    CodeBlockInfo, ElementKind, HeadingInfo, Highlighter, ImageClickEvent, ImageSize,
+    LinkClickEvent, LinkDescription, MarkdownMouseEvent, MarkdownTag, Metadata, MetadataKind,
+    ThemeSource, TocEntry,
+};
+use crate::utils::{
+    as_closing_tag, clipboard_write_text, escape_html, find_autolinks, find_emails, html_depth,
+    is_internal_url, is_unclosed_fence, is_unsafe_url, parse_info_string, plain_text, resolve_url,
+    shift_heading, slugify, take_alert_marker, take_image_size, Callback, CodeInfo,
+    split_html_lines, HtmlCallback, OptionalHtmlCallback,
+};
 
 type Html = HtmlElement<AnyElement>;
 
+/// `make_callback(context, tag, position)` makes the click callback of an element of kind `tag`.
+/// The ancestors of the element are the tags being rendered, without the element itself
 pub fn make_callback(
     context: &RenderContext,
+    tag: MarkdownTag,
     position: Range<usize>,
 ) -> impl Fn(MouseEvent) + 'static {
     let onclick = context.onclick.clone();
+    let mut ancestors = context.ancestors.borrow().clone();
+    if ancestors.last() == Some(&tag) {
+        ancestors.pop();
+    }
     move |x| {
         let click_event = MarkdownMouseEvent {
             mouse_event: x,
             position: position.clone(),
+            tag: tag.clone(),
+            ancestors: ancestors.clone(),
         };
         onclick.call(click_event)
     }
@@ -34,70 +68,707 @@ pub fn make_callback(
 /// all the context needed to render markdown:
 pub struct RenderContext {
     /// syntax used for syntax highlighting
-    syntax_set: SyntaxSet,
+    syntax_set: Rc<SyntaxSet>,
 
     /// theme used for syntax highlighting
     theme: Theme,
 
+    /// themes used instead of `theme` for some languages
+    theme_by_language: Rc<HashMap<String, Theme>>,
+
+    /// highlighter used instead of syntect
+    highlighter: Option<Rc<dyn Highlighter>>,
+
     /// callback to add interactivity to the rendered markdown
     onclick: Callback<MarkdownMouseEvent>,
 
     /// callback used to render links
     render_links: Option<HtmlCallback<LinkDescription>>,
+
+    /// callback mapping the targets of wikilinks to urls
+    wikilink_resolver: Option<Callback<String, String>>,
+
+    /// callback telling if the target of a wikilink exists
+    wikilink_exists: Option<Callback<String, bool>>,
+
+    /// wether wikilinks are parsed, so that links written `[[...]]` are wikilinks
+    wikilinks: bool,
+
+    /// callback called with the frontmatter of the document
+    on_metadata: Option<Callback<Metadata>>,
+
+    /// callback called with each rendering error
+    on_error: Option<Callback<HtmlError>>,
+
+    /// wether to encode email links as html entities
+    obfuscate_emails: bool,
+
+    /// wether to open external links in a new tab
+    external_links_new_tab: bool,
+
+    /// the host of the site, whose links are not external
+    site_host: Option<String>,
+
+    /// the url against which relative link and image urls are resolved
+    base_url: Option<String>,
+
+    /// the `rel` attribute of external links
+    link_rel: Option<String>,
+
+    /// callback called when a link is clicked
+    on_link_click: Option<Callback<LinkClickEvent>>,
+
+    /// callback called when an image is clicked
+    on_image_click: Option<Callback<ImageClickEvent>>,
+
+    /// callback used to render code blocks
+    render_code_block: Option<OptionalHtmlCallback<CodeBlockInfo>>,
+
+    /// callback used to render headings
+    render_heading: Option<HtmlCallback<HeadingInfo>>,
+
+    /// wether to add a button to copy the content of code blocks
+    copy_button: bool,
+
+    /// wether to highlight code with css classes instead of inline styles
+    css_classes: bool,
+
+    /// wether to show line numbers in highlighted code blocks
+    line_numbers: bool,
+
+    /// the language of the code blocks without one
+    default_code_language: Option<String>,
+
+    /// the size above which code blocks are not highlighted, in bytes
+    max_highlight_bytes: Option<usize>,
+
+    /// the languages of the code blocks rendered as diagrams instead of code
+    diagram_languages: Vec<String>,
+
+    /// wether a diagram was rendered
+    has_diagrams: Cell<bool>,
+
+    /// the number of task list markers rendered so far
+    task_count: Cell<usize>,
+
+    /// the text of the task list item being rendered, that labels its checkbox
+    task_label: RefCell<Option<String>>,
+
+    /// the prefix of the classes of github-style alerts
+    alert_class_prefix: String,
+
+    /// classes added to the elements, by kind
+    class_map: HashMap<ElementKind, String>,
+
+    /// the kinds of elements that are not rendered
+    disabled_elements: HashSet<ElementKind>,
+
+    /// the emojis of the shortcodes like `:tada:`, if they are enabled
+    emojis: Option<Rc<HashMap<String, String>>>,
+
+    /// wether to render `:::` containers
+    containers: bool,
+
+    /// the names of the containers that can be rendered, or `None` to allow all of them
+    container_names: Option<Vec<String>>,
+
+    /// wether to render `x^2^` and `H~2~O` as superscript and subscript
+    sub_superscript: bool,
+
+    /// wether to render `==text==` as highlighted text
+    highlight_syntax: bool,
+
+    /// wether to turn bare urls into links
+    autolinks: bool,
+
+    /// wether task list checkboxes are disabled
+    read_only_tasks: bool,
+
+    /// wether to render errors as empty placeholders instead of red boxes
+    hide_errors: bool,
+
+    /// wether to add the position in the source to block elements
+    source_pos: bool,
+
+    /// wether to load images immediately instead of lazily
+    eager_images: bool,
+
+    /// wether to sanitize raw html before inserting it,
+    /// and to remove the links with an unsafe url, like `javascript:`
+    sanitize_html: bool,
+
+    /// wether to align table cells with classes instead of inline styles
+    alignment_classes: bool,
+
+    /// wether to wrap tables in a horizontally scrollable `<div>`
+    responsive_tables: bool,
+
+    /// wether to give unordered lists a class from their bullet character
+    bullet_classes: bool,
+
+    /// wether to render emphasis, strong text and strikethrough with `<i>`, `<b>` and `<s>`
+    /// instead of `<em>`, `<strong>` and `<del>`
+    presentational_tags: bool,
+
+    /// wether the source is streamed, so its last code block may not be closed yet
+    streaming: bool,
+
+    /// wether to render paragraphs as `<span>`, to put the markdown inside a line
+    inline: bool,
+
+    /// wether to add an `id` to headings, derived from their content
+    heading_ids: bool,
+
+    /// the number of levels headings are shifted by
+    heading_offset: u8,
+
+    /// wether to add a `#` link to itself at the end of each heading
+    heading_anchors: bool,
+
+    /// prefix added before the `id` of headings
+    heading_id_prefix: String,
+
+    /// number of times each heading `id` was already used
+    heading_slugs: RefCell<HashMap<String, usize>>,
+
+    /// the tags containing the element being rendered, from the outermost
+    ancestors: RefCell<Vec<MarkdownTag>>,
+
+    /// the headings encountered while rendering
+    toc: RefCell<Vec<TocEntry>>,
+
+    /// the text of each footnote definition, by label, shown when hovering references
+    footnote_texts: HashMap<String, String>,
+
+    /// the labels of the footnote definitions of the document, when they are known.
+    /// A reference to a footnote that is not defined is then rendered as text
+    footnote_labels: Option<HashSet<String>>,
+
+    /// footnotes collected while rendering,
+    /// they are emitted at the end of the document by [`render_footnotes`]
+    footnotes: RefCell<Footnotes>,
 }
 
-impl RenderContext {
-    pub fn new(
-        theme_name: Option<String>,
+/// the footnotes of a document.
+/// Definitions can appear anywhere in the source, so they are
+/// kept here until the whole document has been rendered
+#[derive(Default)]
+struct Footnotes {
+    /// labels of the footnotes, in the order of their first reference
+    labels: Vec<String>,
+
+    /// number of references to each footnote, in the same order as `labels`
+    reference_counts: Vec<usize>,
+
+    /// rendered definitions of the footnotes, by label
+    definitions: HashMap<String, View>,
+}
+
+impl Footnotes {
+    /// `reference(label)` returns the number of the footnote `label`,
+    /// and the number of this reference to it, starting from 1
+    fn reference(&mut self, label: &str) -> (usize, usize) {
+        match self.labels.iter().position(|l| l == label) {
+            Some(i) => {
+                self.reference_counts[i] += 1;
+                (i + 1, self.reference_counts[i])
+            }
+            None => {
+                self.labels.push(label.to_string());
+                self.reference_counts.push(1);
+                (self.labels.len(), 1)
+            }
+        }
+    }
+}
+
+/// `footnote_reference_id(n, k)` gives the id of the reference number `k` to the footnote `n`
+fn footnote_reference_id(n: usize, k: usize) -> String {
+    match k {
+        1 => format!("fnref-{n}"),
+        _ => format!("fnref-{n}-{k}"),
+    }
+}
+
+thread_local! {
+    // the default syntaxes and themes are expensive to load,
+    // so they are loaded only once and shared by all the render contexts
+    #[cfg(feature = "syntax-highlighting")]
+    static DEFAULT_SYNTAX_SET: Rc<SyntaxSet> = Rc::new(SyntaxSet::load_defaults_newlines());
+    #[cfg(feature = "syntax-highlighting")]
+    static DEFAULT_THEME_SET: Rc<ThemeSet> = Rc::new(ThemeSet::load_defaults());
+
+    // without the defaults, nothing is highlighted
+    // unless a syntax set or a highlighter is given
+    #[cfg(not(feature = "syntax-highlighting"))]
+    static DEFAULT_SYNTAX_SET: Rc<SyntaxSet> = Rc::new(SyntaxSet::new());
+    #[cfg(not(feature = "syntax-highlighting"))]
+    static DEFAULT_THEME_SET: Rc<ThemeSet> = Rc::new(ThemeSet::new());
+}
+
+/// the theme used when none is specified, or when the requested one does not exist
+const DEFAULT_THEME: &str = "base16-ocean.light";
+
+/// the options of a [`RenderContext`], set one by one.
+/// All the options are disabled by default, except `sanitize_html`.
+/// The builder can be kept and cloned to build a new context for each render,
+/// with only the options that changed set again, or given to the `context` prop
+/// of [`Markdown`](crate::Markdown) to reuse the same options in several places
+#[derive(Clone, Default)]
+pub struct RenderContextBuilder {
+    theme: Option<ThemeSource>,
+    syntax_set: Option<Rc<SyntaxSet>>,
+    theme_by_language: Rc<HashMap<String, Theme>>,
+    highlighter: Option<Rc<dyn Highlighter>>,
+    pub(crate) onclick: Option<Callback<MarkdownMouseEvent>>,
+    render_links: Option<HtmlCallback<LinkDescription>>,
+    wikilink_resolver: Option<Callback<String, String>>,
+    wikilink_exists: Option<Callback<String, bool>>,
+    wikilinks: bool,
+    obfuscate_emails: bool,
+    external_links_new_tab: bool,
+    site_host: Option<String>,
+    base_url: Option<String>,
+    link_rel: Option<String>,
+    on_link_click: Option<Callback<LinkClickEvent>>,
+    on_image_click: Option<Callback<ImageClickEvent>>,
+    pub(crate) on_metadata: Option<Callback<Metadata>>,
+    pub(crate) on_error: Option<Callback<HtmlError>>,
+    render_code_block: Option<OptionalHtmlCallback<CodeBlockInfo>>,
+    render_heading: Option<HtmlCallback<HeadingInfo>>,
+    copy_button: bool,
+    css_classes: bool,
+    line_numbers: bool,
+    default_code_language: Option<String>,
+    max_highlight_bytes: Option<usize>,
+    diagram_languages: Vec<String>,
+    alert_class_prefix: Option<String>,
+    class_map: HashMap<ElementKind, String>,
+    disabled_elements: HashSet<ElementKind>,
+    emojis: Option<Rc<HashMap<String, String>>>,
+    pub(crate) containers: bool,
+    container_names: Option<Vec<String>>,
+    sub_superscript: bool,
+    highlight_syntax: bool,
+    pub(crate) autolinks: bool,
+    pub(crate) read_only_tasks: bool,
+    hide_errors: bool,
+    pub(crate) source_pos: bool,
+    eager_images: bool,
+    // `None` until it is set, since html is sanitized by default
+    sanitize_html: Option<bool>,
+    alignment_classes: bool,
+    responsive_tables: bool,
+    bullet_classes: bool,
+    presentational_tags: bool,
+    streaming: bool,
+    pub(crate) inline: bool,
+    heading_ids: bool,
+    heading_anchors: bool,
+    heading_offset: u8,
+    heading_id_prefix: Option<String>,
+    footnote_texts: HashMap<String, String>,
+    footnote_labels: Option<HashSet<String>>,
+}
+
+/// `setters!(vis name: Type, ...)` defines a chainable setter for each option,
+/// visible as `vis`
+macro_rules! setters {
+    ($vis:vis $($name:ident: $t:ty,)*) => {
+        $(
+            $vis fn $name(mut self, $name: $t) -> Self {
+                self.$name = $name;
+                self
+            }
+        )*
+    };
+}
+
+impl RenderContextBuilder {
+    setters! {
+        pub
+        theme: Option<ThemeSource>,
+        syntax_set: Option<Rc<SyntaxSet>>,
+        theme_by_language: Rc<HashMap<String, Theme>>,
+        highlighter: Option<Rc<dyn Highlighter>>,
         onclick: Option<Callback<MarkdownMouseEvent>>,
         render_links: Option<HtmlCallback<LinkDescription>>,
-    ) -> Self {
-        let theme_set = ThemeSet::load_defaults();
-        let theme_name = theme_name.unwrap_or("base16-ocean.light".to_string());
-        let theme = theme_set
-            .themes
-            .get(&theme_name)
-            .expect("unknown theme")
-            .clone();
+        wikilink_resolver: Option<Callback<String, String>>,
+        wikilink_exists: Option<Callback<String, bool>>,
+        obfuscate_emails: bool,
+        external_links_new_tab: bool,
+        site_host: Option<String>,
+        base_url: Option<String>,
+        link_rel: Option<String>,
+        on_link_click: Option<Callback<LinkClickEvent>>,
+        on_image_click: Option<Callback<ImageClickEvent>>,
+        on_metadata: Option<Callback<Metadata>>,
+        on_error: Option<Callback<HtmlError>>,
+        render_code_block: Option<OptionalHtmlCallback<CodeBlockInfo>>,
+        render_heading: Option<HtmlCallback<HeadingInfo>>,
+        copy_button: bool,
+        css_classes: bool,
+        line_numbers: bool,
+        default_code_language: Option<String>,
+        max_highlight_bytes: Option<usize>,
+        diagram_languages: Vec<String>,
+        alert_class_prefix: Option<String>,
+        class_map: HashMap<ElementKind, String>,
+        disabled_elements: HashSet<ElementKind>,
+        emojis: Option<Rc<HashMap<String, String>>>,
+        containers: bool,
+        container_names: Option<Vec<String>>,
+        sub_superscript: bool,
+        highlight_syntax: bool,
+        read_only_tasks: bool,
+        hide_errors: bool,
+        source_pos: bool,
+        eager_images: bool,
+        alignment_classes: bool,
+        responsive_tables: bool,
+        bullet_classes: bool,
+        presentational_tags: bool,
+        inline: bool,
+        heading_ids: bool,
+        heading_anchors: bool,
+        heading_offset: u8,
+        heading_id_prefix: Option<String>,
+    }
+
+    // the options set by the `Markdown` component from its props and the document
+    setters! {
+        pub(crate)
+        autolinks: bool,
+        wikilinks: bool,
+        streaming: bool,
+        footnote_texts: HashMap<String, String>,
+        footnote_labels: Option<HashSet<String>>,
+    }
+
+    /// wether to sanitize raw html before inserting it, `true` by default
+    pub fn sanitize_html(mut self, sanitize_html: bool) -> Self {
+        self.sanitize_html = Some(sanitize_html);
+        self
+    }
+
+    /// `build()` creates the context, loading the default syntaxes and themes if needed
+    pub fn build(self) -> RenderContext {
+        let RenderContextBuilder {
+            theme,
+            syntax_set,
+            theme_by_language,
+            highlighter,
+            onclick,
+            render_links,
+            wikilink_resolver,
+            wikilink_exists,
+            wikilinks,
+            obfuscate_emails,
+            external_links_new_tab,
+            site_host,
+            base_url,
+            link_rel,
+            on_link_click,
+            on_image_click,
+            on_metadata,
+            on_error,
+            render_code_block,
+            render_heading,
+            copy_button,
+            css_classes,
+            line_numbers,
+            default_code_language,
+            max_highlight_bytes,
+            diagram_languages,
+            alert_class_prefix,
+            class_map,
+            disabled_elements,
+            emojis,
+            containers,
+            container_names,
+            sub_superscript,
+            highlight_syntax,
+            autolinks,
+            read_only_tasks,
+            hide_errors,
+            source_pos,
+            eager_images,
+            sanitize_html,
+            alignment_classes,
+            responsive_tables,
+            bullet_classes,
+            presentational_tags,
+            streaming,
+            inline,
+            heading_ids,
+            heading_anchors,
+            heading_offset,
+            heading_id_prefix,
+            footnote_texts,
+            footnote_labels,
+        } = self;
+        let theme_set = DEFAULT_THEME_SET.with(Rc::clone);
+        let theme = match theme {
+            Some(ThemeSource::Custom(theme)) => theme,
+            Some(ThemeSource::Named(name)) if theme_set.themes.contains_key(&name) => {
+                theme_set.themes[&name].clone()
+            }
+            Some(ThemeSource::Named(name)) => {
+                log::warn!("unknown theme `{name}`, falling back to `{DEFAULT_THEME}`");
+                theme_set.themes.get(DEFAULT_THEME).cloned().unwrap_or_default()
+            }
+            None => theme_set.themes.get(DEFAULT_THEME).cloned().unwrap_or_default(),
+        };
 
-        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let syntax_set = syntax_set.unwrap_or_else(|| DEFAULT_SYNTAX_SET.with(Rc::clone));
 
         RenderContext {
             syntax_set,
             theme,
+            theme_by_language,
+            highlighter,
             onclick: onclick.unwrap_or(Callback::new(|_| ())),
             render_links,
+            wikilink_resolver,
+            wikilink_exists,
+            wikilinks,
+            obfuscate_emails,
+            external_links_new_tab,
+            site_host,
+            base_url,
+            link_rel,
+            on_link_click,
+            on_image_click,
+            on_metadata,
+            on_error,
+            render_code_block,
+            render_heading,
+            copy_button,
+            css_classes,
+            line_numbers,
+            default_code_language,
+            max_highlight_bytes,
+            diagram_languages,
+            has_diagrams: Cell::new(false),
+            task_count: Cell::new(0),
+            task_label: RefCell::new(None),
+            alert_class_prefix: alert_class_prefix.unwrap_or("alert".to_string()),
+            class_map,
+            disabled_elements,
+            emojis,
+            containers,
+            container_names,
+            sub_superscript,
+            highlight_syntax,
+            autolinks,
+            read_only_tasks,
+            hide_errors,
+            source_pos,
+            eager_images,
+            sanitize_html: sanitize_html.unwrap_or(true),
+            alignment_classes,
+            responsive_tables,
+            bullet_classes,
+            presentational_tags,
+            streaming,
+            inline,
+            heading_ids,
+            heading_anchors,
+            heading_offset,
+            heading_id_prefix: heading_id_prefix.unwrap_or_default(),
+            heading_slugs: RefCell::new(HashMap::new()),
+            ancestors: RefCell::new(Vec::new()),
+            toc: RefCell::new(Vec::new()),
+            footnote_texts,
+            footnote_labels,
+            footnotes: RefCell::new(Footnotes::default()),
+        }
+    }
+}
+
+impl RenderContext {
+    /// `builder()` starts to build a context, see [`RenderContextBuilder`]
+    pub fn builder() -> RenderContextBuilder {
+        RenderContextBuilder::default()
+    }
+
+    /// `is_disabled(kind)` tells wether the elements of kind `kind` are not rendered
+    fn is_disabled(&self, kind: ElementKind) -> bool {
+        self.disabled_elements.contains(&kind)
+    }
+
+    /// `theme_for(lang)` gives the theme used to highlight code in the language `lang`
+    fn theme_for(&self, lang: &str) -> &Theme {
+        self.theme_by_language.get(lang).unwrap_or(&self.theme)
+    }
+
+    /// `has_diagrams()` tells wether diagrams were rendered,
+    /// and need to be processed by a javascript library
+    pub fn has_diagrams(&self) -> bool {
+        self.has_diagrams.get()
+    }
+
+    /// `state_hash()` is the hash of the state left by the elements rendered so far,
+    /// that changes how the next ones are rendered: the ids of the headings,
+    /// the number of tasks, and the numbers of the footnotes
+    pub(crate) fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let mut slugs: Vec<_> = self.heading_slugs.borrow().clone().into_iter().collect();
+        slugs.sort();
+        slugs.hash(&mut hasher);
+        self.task_count.get().hash(&mut hasher);
+        let footnotes = self.footnotes.borrow();
+        footnotes.labels.hash(&mut hasher);
+        footnotes.reference_counts.hash(&mut hasher);
+        let mut texts: Vec<_> = self.footnote_texts.iter().collect();
+        texts.sort();
+        texts.hash(&mut hasher);
+        let mut labels: Vec<_> = self.footnote_labels.iter().flatten().collect();
+        labels.sort();
+        labels.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// `take_toc()` returns all the headings rendered so far, in order
+    pub fn take_toc(&self) -> Vec<TocEntry> {
+        self.toc.take()
+    }
+
+    /// `resolve_url(url)` resolves the relative `url` against the `base_url`, if there is one
+    fn resolve_url(&self, url: &str) -> String {
+        match &self.base_url {
+            Some(base) => resolve_url(url, base),
+            None => url.to_string(),
         }
     }
+
+    /// `class(kind, base)` gives the class of an element of kind `kind`:
+    /// the class `base` of the element, followed by the one from the class map
+    fn class(&self, kind: ElementKind, base: Option<String>) -> Option<String> {
+        match (base, self.class_map.get(&kind)) {
+            (Some(base), Some(class)) => Some(format!("{base} {class}")),
+            (base, class) => base.or(class.cloned()),
+        }
+    }
+
+    /// `container_marker(events)` gives the container marker
+    /// made of the content `events` of a paragraph, if it is one
+    fn container_marker(&self, events: &[(Event, Range<usize>)]) -> Option<Marker> {
+        match parse_marker(marker_text(events)?)? {
+            Marker::Open { name, .. } if !self.container_allowed(&name) => None,
+            marker => Some(marker),
+        }
+    }
+
+    /// `container_allowed(name)` checks if containers named `name` can be rendered
+    fn container_allowed(&self, name: &str) -> bool {
+        match &self.container_names {
+            Some(names) => names.iter().any(|x| x == name),
+            None => true,
+        }
+    }
+
+    /// `heading_id(slug)` returns a unique `id` for a heading with the slug `slug`,
+    /// after the `heading_id_prefix`. Duplicate ids get a numeric suffix, like on github.
+    fn heading_id(&self, slug: &str) -> String {
+        let slug = format!("{}{slug}", self.heading_id_prefix);
+        let mut slugs = self.heading_slugs.borrow_mut();
+        let count = slugs.entry(slug.clone()).or_insert(0);
+        let id = match *count {
+            0 => slug,
+            n => format!("{slug}-{n}"),
+        };
+        *count += 1;
+        id
+    }
 }
 
-pub struct HtmlError(String);
+/// an error that happened while rendering an element
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HtmlError {
+    /// the element uses a feature that is not supported
+    UnsupportedFeature(String),
+
+    /// a math expression could not be rendered
+    InvalidMath(String),
+
+    /// some raw html could not be sanitized
+    Sanitization(String),
+
+    /// the events given to the renderer are not well formed,
+    /// for example after a wrong `transform_events`
+    MalformedEvents(String),
+
+    /// a footnote is defined, with this label, but never referenced
+    UnusedFootnote(String),
+}
 
 impl HtmlError {
-    fn err<T>(message: &str) -> Result<T, Self> {
-        Err(HtmlError(message.to_string()))
+    /// the description of the error
+    pub fn message(&self) -> &str {
+        match self {
+            HtmlError::UnsupportedFeature(m)
+            | HtmlError::InvalidMath(m)
+            | HtmlError::Sanitization(m)
+            | HtmlError::MalformedEvents(m)
+            | HtmlError::UnusedFootnote(m) => m,
+        }
     }
 }
 
-impl ToString for HtmlError {
-    fn to_string(&self) -> String {
-        self.0.to_owned()
+impl std::fmt::Display for HtmlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HtmlError::UnsupportedFeature(m) => write!(f, "unsupported feature: {m}"),
+            HtmlError::InvalidMath(m) => write!(f, "invalid math: {m}"),
+            HtmlError::Sanitization(m) => write!(f, "could not sanitize html: {m}"),
+            HtmlError::MalformedEvents(m) => write!(f, "malformed events: {m}"),
+            HtmlError::UnusedFootnote(m) => write!(f, "the footnote [^{m}] is never referenced"),
+        }
     }
 }
 
+impl std::error::Error for HtmlError {}
+
 use Event::*;
 
+/// the renderer of a stream of markdown events, with their range in the source.
+/// It is an iterator over the rendered top-level elements, and it keeps its place
+/// in the stream, so a long document can be rendered in chunks:
+/// ```ignore
+/// let context = RenderContext::builder().build();
+/// let mut events = ParserOffsetIter::new_ext(src, Options::all(), false);
+/// let mut renderer = Renderer::new(&context, src, &mut events);
+/// // the first 20 blocks now
+/// let first = renderer.take_blocks(20);
+/// // and the next ones later, one at a time, with their position in the source
+/// while let Some((range, html)) = renderer.next_block() { /* ... */ }
+/// // the footnotes referenced by all the blocks rendered
+/// let footnotes = render_footnotes(&context);
+/// ```
+/// The events are rendered as they are: the preprocessing done by the [`Markdown`] component,
+/// like `autolinks`, `containers` or `latex_delimiters`, does not apply.
+///
+/// [`Markdown`]: crate::Markdown
 pub struct Renderer<'a, 'c, I>
 where
     I: Iterator<Item = (Event<'a>, Range<usize>)>,
 {
     context: &'a RenderContext,
+    /// the markdown source of the events
+    source: &'a str,
     stream: &'c mut I,
     // TODO: Vec<Alignment> to &[Alignment] to avoid cloning.
     // But it requires to provide the right lifetime
     column_alignment: Option<Vec<Alignment>>,
     cell_index: usize,
+    /// wether the cells being rendered are in the head of a table
+    in_table_head: bool,
     end_tag: Option<TagEnd>,
+    /// wether the closing tag was already consumed
+    finished: bool,
+    /// the position in the source of the last element rendered
+    block_range: Range<usize>,
 }
 
 impl<'a, 'c, I> Iterator for Renderer<'a, 'c, I>
@@ -107,38 +778,94 @@ where
     type Item = Html;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (item, range) = self.stream.next()?;
-        let range = range.clone();
+        if self.finished {
+            return None;
+        }
+        let (item, range) = loop {
+            match self.stream.next()? {
+                // a definition is shown with the footnotes, at the end of the document,
+                // so it leaves nothing where it is written
+                (Start(tag @ Tag::FootnoteDefinition(_)), _) => self.store_footnote_definition(tag),
+                event => break event,
+            }
+        };
+        self.block_range = range.clone();
 
         let rendered = match item {
-            Start(t) => self.render_tag(t, range),
+            Start(t) if element_kind(&t).is_some_and(|k| self.context.is_disabled(k)) => {
+                self.collect_children(&t);
+                Ok(view! { <span></span> }.into_any())
+            }
+            Start(t) if self.source_at(range.clone()).is_err() => {
+                // the tag is skipped with its content, so the rest of the document is rendered
+                self.collect_children(&t);
+                Err(out_of_source(&range))
+            }
+            Start(t) => {
+                let source_pos = self.context.source_pos && is_block(&t);
+                self.context.ancestors.borrow_mut().push(MarkdownTag::from(&t));
+                let rendered = self.render_tag(t, range.clone());
+                self.context.ancestors.borrow_mut().pop();
+                match source_pos {
+                    true => rendered.map(|html| with_source_pos(html, &range)),
+                    false => rendered,
+                }
+            }
             End(end) => {
                 // check if the closing tag is the tag that was open
                 // when this renderer was created
                 match self.end_tag {
                     Some(t) if t == end => return None,
-                    Some(_) => panic!("wrong closing tag"),
-                    None => panic!("didn't expect a closing tag"),
+                    Some(t) => Err(HtmlError::MalformedEvents(format!(
+                        "expected the closing tag {t:?}, got {end:?}"
+                    ))),
+                    None => Err(HtmlError::MalformedEvents(format!(
+                        "unexpected closing tag {end:?}"
+                    ))),
                 }
             }
-            Text(s) => Ok(render_text(self.context, &s, range)),
+            Text(s) => self.is_escaped(&s, range.start).and_then(|escaped| {
+                let in_link = self.context.ancestors.borrow().iter().any(|t| {
+                    matches!(t, MarkdownTag::Link { .. } | MarkdownTag::Image { .. })
+                });
+                match self.context.autolinks && !in_link {
+                    true => render_autolinks(self.context, &s, escaped, range),
+                    false => Ok(render_text(self.context, &s, escaped, range)),
+                }
+            }),
+            Code(_) if self.context.is_disabled(ElementKind::Code) => {
+                Ok(view! { <span></span> }.into_any())
+            }
             Code(s) => Ok(render_code(self.context, &s, range)),
+            Html(s) if self.is_inline_html(&s) => {
+                let html = self.collect_inline_html(s.to_string());
+                Ok(render_inline_html(self.context, &html, range))
+            }
             Html(s) => Ok(render_html(self.context, &s, range)),
-            FootnoteReference(_) => HtmlError::err("do not support footnote refs yet"),
-            SoftBreak => Ok(self.next()?),
+            FootnoteReference(label) => Ok(render_footnote_reference(self.context, &label)),
+            // the text around a soft break is in separate spans, so it needs its own space
+            SoftBreak => Ok(view! {<span>{"\n"}</span>}.into_any()),
             HardBreak => Ok(view! {<br/>}.into_any()),
+            Rule if self.context.source_pos => {
+                Ok(with_source_pos(render_rule(self.context, range.clone()), &range))
+            }
             Rule => Ok(render_rule(self.context, range)),
             TaskListMarker(m) => Ok(render_tasklist_marker(self.context, m, range)),
-            Math(_, content) => Ok(view!{
-                <div class="math">
-                    {render_text(self.context, &content, range)}
-                </div>
-            }.into_any()),
+            Math(_, content) => Ok(render_math(self.context, &content, range)),
+            // the events added by a newer version of the parser
+            #[allow(unreachable_patterns)]
+            event => Ok(render_unknown(&format!("{event:?}"))),
         };
 
         Some(rendered.unwrap_or_else(|e| {
+            if let Some(f) = &self.context.on_error {
+                f.call(e.clone())
+            }
+            if self.context.hide_errors {
+                return view! { <span class="error"></span> }.into_any();
+            }
             view! {
-            <span class="error" style="border: 1px solid red">
+            <span class="error" role="alert" style="border: 1px solid red">
                 {e.to_string()}
                 <br/>
             </span>
@@ -152,96 +879,491 @@ impl<'a, 'c, I> Renderer<'a, 'c, I>
 where
     I: Iterator<Item = (Event<'a>, Range<usize>)>,
 {
-    pub fn new(context: &'a RenderContext, events: &'c mut I) -> Self {
+    /// `take_blocks(n)` renders the next `n` top-level elements of the document.
+    /// The renderer is the continuation: it can be called again, or iterated,
+    /// to render the rest. Footnotes are only collected from the rendered part
+    pub fn take_blocks(&mut self, n: usize) -> View {
+        self.by_ref().take(n).collect_view()
+    }
+
+    /// `next_block()` renders the next top-level element of the document,
+    /// and gives it with its position in the source
+    pub fn next_block(&mut self) -> Option<(Range<usize>, Html)> {
+        let html = self.next()?;
+        Some((self.block_range.clone(), html))
+    }
+
+    /// `new(context, source, events)` renders the `events` of the markdown `source`
+    /// with `context`
+    pub fn new(context: &'a RenderContext, source: &'a str, events: &'c mut I) -> Self {
         Self {
             context,
+            source,
             stream: events,
             column_alignment: None,
             cell_index: 0,
+            in_table_head: false,
             end_tag: None,
+            finished: false,
+            block_range: 0..0,
         }
     }
 
+    /// `store_footnote_definition(tag)` renders the footnote definition `tag`,
+    /// and keeps it for [`render_footnotes`]
+    fn store_footnote_definition(&mut self, tag: Tag<'a>) {
+        let Tag::FootnoteDefinition(label) = &tag else {
+            return;
+        };
+        let label = label.to_string();
+        self.context.ancestors.borrow_mut().push(MarkdownTag::from(&tag));
+        let content = self.children(tag);
+        self.context.ancestors.borrow_mut().pop();
+        self.context.footnotes.borrow_mut().definitions.insert(label, content);
+    }
+
+    /// `source_at(range)` gives the markdown source at `range`.
+    /// The ranges come with the events, so they may be out of the source
+    /// when the events were changed by `transform_events`
+    fn source_at(&self, range: Range<usize>) -> Result<&'a str, HtmlError> {
+        self.source.get(range.clone()).ok_or_else(|| out_of_source(&range))
+    }
+
+    /// `is_escaped(text, start)` checks if the text `text`, found at `start` in the source,
+    /// starts with an escaped character, like `\^`, that should not start an inline extension
+    fn is_escaped(&self, text: &str, start: usize) -> Result<bool, HtmlError> {
+        let before = self.source_at(0..start)?;
+        let after = self.source_at(start..self.source.len())?;
+        Ok(before.ends_with('\\') || (after.starts_with('\\') && !text.starts_with('\\')))
+    }
+
+    /// `is_inline_html(html)` checks if the html event `html` is in the middle of some text.
+    /// Html blocks are made of whole lines, so they end with a newline
+    fn is_inline_html(&self, html: &str) -> bool {
+        use MarkdownTag::*;
+        match self.context.ancestors.borrow().last() {
+            Some(Paragraph | Heading(_) | TableCell | Emphasis | Strong | Strikethrough) => true,
+            Some(Link { .. } | Image { .. }) => true,
+            Some(Item) => !html.ends_with('\n'),
+            _ => false,
+        }
+    }
+
+    /// `collect_inline_html(html)` consumes the events following the inline html `html`
+    /// until its tag is closed, so that `<kbd>Ctrl</kbd>` is rendered as a single element.
+    /// Returns the html of all these events
+    fn collect_inline_html(&mut self, mut html: String) -> String {
+        let mut depth = html_depth(&html);
+        // the closing tags of the markdown elements opened inside the html
+        let mut closing_tags = Vec::new();
+        while depth > 0 {
+            let Some((event, _)) = self.stream.next() else {
+                break;
+            };
+            match event {
+                Html(s) => {
+                    depth += html_depth(&s);
+                    html.push_str(&s)
+                }
+                Text(s) => html.push_str(&escape_html(&s)),
+                Code(s) => html.push_str(&format!("<code>{}</code>", escape_html(&s))),
+                SoftBreak => html.push('\n'),
+                HardBreak => html.push_str("<br>"),
+                Start(tag) => {
+                    let (open, close) = inline_tag_html(self.context, &tag);
+                    html.push_str(&open);
+                    closing_tags.push(close);
+                }
+                End(_) => match closing_tags.pop() {
+                    Some(close) => html.push_str(close),
+                    // the element containing the html ended before the html was closed
+                    None => {
+                        self.finished = true;
+                        break;
+                    }
+                },
+                _ => (),
+            }
+        }
+        html
+    }
+
     fn children(&mut self, tag: Tag<'a>) -> View {
         let sub_renderer = Renderer {
             context: self.context,
+            source: self.source,
             stream: self.stream,
             column_alignment: self.column_alignment.clone(),
             cell_index: 0,
-            end_tag: Some(as_closing_tag(&tag)),
+            in_table_head: self.in_table_head,
+            end_tag: as_closing_tag(&tag),
+            finished: false,
+            block_range: 0..0,
         };
         sub_renderer.collect_view()
     }
 
-    fn children_text(&mut self, tag: Tag<'a>) -> Option<String> {
-        let text = match self.stream.next() {
-            Some((Event::Text(s), _)) => Some(s.to_string()),
-            None => None,
-            _ => panic!("expected string event, got something else"),
-        };
+    /// `collect_children(tag)` consumes all the events inside `tag`,
+    /// including its closing tag, and returns them without the closing tag
+    fn collect_children(&mut self, tag: &Tag<'a>) -> Vec<(Event<'a>, Range<usize>)> {
+        let end = as_closing_tag(tag);
+        let mut depth = 0;
+        let mut events = Vec::new();
+        for (event, range) in self.stream.by_ref() {
+            match (&event, end) {
+                (Start(t), Some(_)) if as_closing_tag(t) == end => depth += 1,
+                (End(t), Some(end)) if *t == end && depth == 0 => break,
+                (End(t), Some(end)) if *t == end => depth -= 1,
+                // the closing tag of a tag unknown to this crate is not known either,
+                // so all the tags are counted to find it
+                (Start(_), None) => depth += 1,
+                (End(_), None) if depth == 0 => break,
+                (End(_), None) => depth -= 1,
+                _ => (),
+            }
+            events.push((event, range));
+        }
+        events
+    }
 
-        let end_tag = &self
-            .stream
-            .next()
-            .expect("this event should be the closing tag")
-            .0;
-        assert!(end_tag == &Event::End(as_closing_tag(&tag)));
+    /// `collect_container()` consumes the events of the container that was just opened,
+    /// including its closing marker, and returns them without the closing marker
+    fn collect_container(&mut self) -> Vec<(Event<'a>, Range<usize>)> {
+        let mut events = Vec::new();
+        let mut depth = 0;
+        // the number of containers opened inside this one
+        let mut nested = 0;
+        while let Some((event, range)) = self.stream.next() {
+            match &event {
+                Start(_) => depth += 1,
+                // the element containing the container ended before the container was closed
+                End(_) if depth == 0 => {
+                    self.finished = true;
+                    break;
+                }
+                End(_) => depth -= 1,
+                _ => (),
+            }
+            let paragraph_end = matches!(event, End(TagEnd::Paragraph)) && depth == 0;
+            events.push((event, range));
+            let n = events.len();
+            if !paragraph_end || n < 3 || !matches!(events[n - 3].0, Start(Tag::Paragraph)) {
+                continue;
+            }
 
-        text
+            let content = &events[n - 2..n - 1];
+            match self.context.container_marker(content) {
+                Some(Marker::Open { .. }) => nested += 1,
+                Some(Marker::Close) if nested > 0 => nested -= 1,
+                Some(Marker::Close) => {
+                    events.truncate(n - 3);
+                    break;
+                }
+                None => (),
+            }
+        }
+        events
+    }
+
+    /// `render_container(name, title)` renders the container that was just opened
+    /// with the marker `:::{name} {title}`
+    fn render_container(&mut self, name: &str, title: String) -> Html {
+        let events = self.collect_container();
+        if name == "details" || name == "details-open" {
+            let title = match title.is_empty() {
+                true => "Details".to_string(),
+                false => title,
+            };
+            return view! {
+                <details open={name == "details-open"}>
+                    <summary>{title}</summary>
+                    {self.render_events(events)}
+                </details>
+            }
+            .into_any();
+        }
+
+        let title = (!title.is_empty()).then(|| {
+            view! { <p class=format!("{name}-title")>{title}</p> }
+        });
+        view! {
+            <div class=name.to_string()>
+                {title}
+                {self.render_events(events)}
+            </div>
+        }
+        .into_any()
+    }
+
+    /// `render_events(events)` renders events that were
+    /// previously collected with [`collect_children`](Self::collect_children)
+    fn render_events(&self, events: Vec<(Event<'a>, Range<usize>)>) -> View {
+        let mut stream = events.into_iter();
+        let sub_renderer = Renderer {
+            context: self.context,
+            source: self.source,
+            stream: &mut stream,
+            column_alignment: self.column_alignment.clone(),
+            cell_index: 0,
+            in_table_head: self.in_table_head,
+            end_tag: None,
+            finished: false,
+            block_range: 0..0,
+        };
+        sub_renderer.collect_view()
+    }
+
+    /// `children_text(tag)` consumes the text inside `tag`, and its closing tag.
+    /// Returns `None` if there is no text
+    fn children_text(&mut self, tag: Tag<'a>) -> Result<Option<String>, HtmlError> {
+        let end_tag = as_closing_tag(&tag);
+        let mut text: Option<String> = None;
+        let mut unexpected = None;
+        loop {
+            match self.stream.next() {
+                Some((Event::Text(s), _)) => text.get_or_insert_with(String::new).push_str(&s),
+                Some((Event::End(end), _)) if Some(end) == end_tag => break,
+                // the other events are skipped, so the closing tag is still consumed
+                Some((event, _)) => {
+                    unexpected.get_or_insert(format!("unexpected {event:?} in {tag:?}"));
+                }
+                None => {
+                    unexpected.get_or_insert(format!("{tag:?} is not closed"));
+                    break;
+                }
+            }
+        }
+        match unexpected {
+            Some(message) => Err(HtmlError::MalformedEvents(message)),
+            None => Ok(text),
+        }
     }
 
     fn render_tag(&mut self, tag: Tag<'a>, range: Range<usize>) -> Result<Html, HtmlError> {
         Ok(match tag.clone() {
-            Tag::Paragraph => view! {<p>{self.children(tag)}</p>}.into_any(),
-            Tag::Heading { level, .. } => render_heading(level, self.children(tag)),
-            Tag::BlockQuote => view! {
-                <blockquote>
+            Tag::Paragraph if self.context.inline => view! {
+                <span class=self.context.class(ElementKind::Paragraph, None)>
                     {self.children(tag)}
-                </blockquote>
+                </span>
             }
             .into_any(),
-            Tag::CodeBlock(k) => {
-                render_code_block(self.context, self.children_text(tag), &k, range)
+            Tag::Paragraph if self.context.containers => {
+                let events = self.collect_children(&tag);
+                match self.context.container_marker(&events) {
+                    Some(Marker::Open { name, title }) => self.render_container(&name, title),
+                    // a closing marker without container
+                    Some(Marker::Close) => view! { <div></div>}.into_any(),
+                    None => view! {
+                        <p class=self.context.class(ElementKind::Paragraph, None)>
+                            {self.render_events(events)}
+                        </p>
+                    }
+                    .into_any(),
+                }
+            }
+            Tag::Paragraph => view! {
+                <p class=self.context.class(ElementKind::Paragraph, None)>
+                    {self.children(tag)}
+                </p>
             }
-            Tag::List(Some(n0)) => view! {
-            <ol start=n0 as i32>
-                {self.children(tag)}
-            </ol>}
             .into_any(),
-            Tag::List(None) => view! { <ul>{self.children(tag)}</ul>}.into_any(),
-            Tag::Item => view! { <li>{self.children(tag)}</li>}.into_any(),
+            Tag::Heading { level, id, .. } => {
+                let level = shift_heading(level, self.context.heading_offset);
+                let events = self.collect_children(&tag);
+                let text = plain_text(&events);
+                // an id written after the heading, like `# Title {#custom}`,
+                // is used instead of the slug of the text, and always rendered
+                let explicit = id.is_some();
+                let slug = id.map_or_else(|| slugify(&text), |id| id.to_string());
+                let id = self.context.heading_id(&slug);
+                self.context.toc.borrow_mut().push(TocEntry {
+                    level,
+                    id: id.clone(),
+                    text: text.clone(),
+                });
+                let content = self.render_events(events);
+                if let Some(f) = &self.context.render_heading {
+                    return Ok(f.call(HeadingInfo {
+                        level,
+                        id,
+                        text,
+                        content,
+                    }));
+                }
+                let class = self.context.class(ElementKind::Heading, None);
+                if self.context.heading_anchors {
+                    let anchor = view! {
+                        <a class="heading-anchor" href=format!("#{id}") aria-hidden="true">"#"</a>
+                    };
+                    let content = view! {<>{content}{anchor}</>};
+                    return Ok(render_heading(level, Some(id), class, content));
+                }
+                let id = (self.context.heading_ids || explicit).then_some(id);
+                render_heading(level, id, class, content)
+            }
+            Tag::BlockQuote => {
+                // the blockquote itself is already in the ancestors, so the depth starts at 1
+                let depth = {
+                    let ancestors = self.context.ancestors.borrow();
+                    ancestors.iter().filter(|t| **t == MarkdownTag::BlockQuote).count()
+                };
+                let mut events = self.collect_children(&tag);
+                match take_alert_marker(&mut events) {
+                    Some((kind, title)) => {
+                        let prefix = &self.context.alert_class_prefix;
+                        let class = format!("{prefix} {prefix}-{kind}");
+                        let class = self.context.class(ElementKind::BlockQuote, Some(class));
+                        view! {
+                            <blockquote class=class data-quote-depth=depth>
+                                <p class=format!("{prefix}-title")>{title}</p>
+                                {self.render_events(events)}
+                            </blockquote>
+                        }
+                        .into_any()
+                    }
+                    None => view! {
+                        <blockquote class=self.context.class(ElementKind::BlockQuote, None)
+                            data-quote-depth=depth>
+                            {self.render_events(events)}
+                        </blockquote>
+                    }
+                    .into_any(),
+                }
+            }
+            Tag::CodeBlock(k) => {
+                let open = self.context.streaming
+                    && matches!(k, CodeBlockKind::Fenced(_))
+                    && is_unclosed_fence(self.source_at(range.clone())?);
+                render_code_block(self.context, self.children_text(tag)?, &k, open, range)
+            }
+            Tag::List(Some(n0)) => {
+                // like github, the `start` attribute is only written when it is needed
+                let start = (n0 != 1).then_some(n0 as i32);
+                view! {
+                <ol start=start class=self.context.class(ElementKind::List, None)>
+                    {self.children(tag)}
+                </ol>}
+                .into_any()
+            }
+            Tag::List(None) => {
+                // the range starts at the marker of the first item
+                let class = match self.context.bullet_classes {
+                    true => {
+                        let bullet = self.source_at(range)?.trim_start().chars().next();
+                        Some(bullet_class(bullet.unwrap_or('-')).to_string())
+                    }
+                    false => None,
+                };
+                view! {
+                <ul class=self.context.class(ElementKind::List, class)>
+                    {self.children(tag)}
+                </ul>}
+                .into_any()
+            }
+            Tag::Item => {
+                let events = self.collect_children(&tag);
+                // in loose lists, the marker is inside a paragraph
+                let is_task = events
+                    .iter()
+                    .take(2)
+                    .any(|(e, _)| matches!(e, TaskListMarker(_)));
+                let class = is_task.then(|| "task-list-item".to_string());
+                if is_task {
+                    // the text of a sublist does not describe the task
+                    let end = events
+                        .iter()
+                        .position(|(e, _)| matches!(e, Start(Tag::List(_))))
+                        .unwrap_or(events.len());
+                    let label = plain_text(&events[..end]).trim().to_string();
+                    *self.context.task_label.borrow_mut() = Some(label);
+                }
+                view! {
+                    <li class=self.context.class(ElementKind::ListItem, class)
+                        style=is_task.then_some("list-style: none")>
+                        {self.render_events(events)}
+                    </li>
+                }
+                .into_any()
+            }
             Tag::Table(align) => {
                 self.column_alignment = Some(align);
-                view! { <table>{self.children(tag)}</table>}.into_any()
+                let table = view! {
+                    <table class=self.context.class(ElementKind::Table, None)>
+                        {self.children(tag)}
+                    </table>
+                };
+                match self.context.responsive_tables {
+                    true => view! {
+                        <div class="table-wrapper" style="overflow-x:auto">{table}</div>
+                    }
+                    .into_any(),
+                    false => table.into_any(),
+                }
             }
-            Tag::TableHead => view! {
-                <thead>{self.children(tag)}</thead>
+            Tag::TableHead => {
+                self.in_table_head = true;
+                let content = self.children(tag);
+                self.in_table_head = false;
+                view! { <thead>{content}</thead> }.into_any()
             }
-            .into_any(),
             Tag::TableRow => view! {
                 <tr>{self.children(tag)}</tr>
             }
             .into_any(),
             Tag::TableCell => {
-                let align = self.column_alignment.clone().unwrap()[self.cell_index];
+                let align = self
+                    .column_alignment
+                    .as_ref()
+                    .and_then(|a| a.get(self.cell_index).copied())
+                    .unwrap_or(Alignment::None);
                 self.cell_index += 1;
-                render_cell(self.children(tag), &align)
+                let classes = self.context.alignment_classes;
+                render_cell(self.children(tag), &align, classes, self.in_table_head)
+            }
+            Tag::Emphasis if self.context.presentational_tags => {
+                view! { <i>{self.children(tag)}</i>}.into_any()
+            }
+            Tag::Emphasis => view! { <em>{self.children(tag)}</em>}.into_any(),
+            Tag::Strong if self.context.presentational_tags => {
+                view! { <b>{self.children(tag)}</b>}.into_any()
+            }
+            Tag::Strong => view! { <strong>{self.children(tag)}</strong>}.into_any(),
+            // with a single tilde, like `H~2~O`, this is a subscript
+            Tag::Strikethrough
+                if self.context.sub_superscript
+                    && !self.source_at(range.clone())?.starts_with("~~") =>
+            {
+                view! { <sub>{self.children(tag)}</sub>}.into_any()
+            }
+            Tag::Strikethrough if self.context.presentational_tags => {
+                view! { <s>{self.children(tag)}</s>}.into_any()
             }
-            Tag::Emphasis => view! { <i>{self.children(tag)}</i>}.into_any(),
-            Tag::Strong => view! { <b>{self.children(tag)}</b>}.into_any(),
-            Tag::Strikethrough => view! { <s>{self.children(tag)}</s>}.into_any(),
+            Tag::Strikethrough => view! { <del>{self.children(tag)}</del>}.into_any(),
             Tag::Image {
                 link_type,
                 dest_url,
                 title,
                 ..
             } => {
+                let events = self.collect_children(&tag);
+                // urls can only contain spaces between angle brackets,
+                // so the size is usually at the end of the title
+                let (url, url_size) = take_image_size(&dest_url);
+                let url = self.context.resolve_url(url);
+                let (title, title_size) = take_image_size(&title);
+                let size = match url_size == ImageSize::default() {
+                    true => title_size,
+                    false => url_size,
+                };
                 let description = LinkDescription {
-                    url: dest_url.to_string(),
+                    url: url.to_string(),
                     title: title.to_string(),
-                    content: self.children(tag),
+                    text: plain_text(&events),
+                    content: self.render_events(events),
                     link_type,
+                    broken: false,
                     image: true,
+                    size,
                 };
                 render_link(self.context, description)?
             }
@@ -251,170 +1373,928 @@ where
                 title,
                 ..
             } => {
+                let events = self.collect_children(&tag);
+                let mut url = dest_url.to_string();
+                if link_type == LinkType::Email && !url.starts_with("mailto:") {
+                    url = format!("mailto:{url}");
+                }
+                let mut broken = false;
+                if self.context.wikilinks && self.source_at(range)?.starts_with("[[") {
+                    if let Some(exists) = &self.context.wikilink_exists {
+                        broken = !exists.call(url.clone());
+                    }
+                    if let Some(resolver) = &self.context.wikilink_resolver {
+                        url = resolver.call(url);
+                    }
+                }
+                let url = self.context.resolve_url(&url);
                 let description = LinkDescription {
-                    url: dest_url.to_string(),
+                    url,
+                    broken,
                     title: title.to_string(),
-                    content: self.children(tag),
+                    text: plain_text(&events),
+                    content: self.render_events(events),
                     link_type,
                     image: false,
+                    size: ImageSize::default(),
                 };
                 render_link(self.context, description)?
             }
-            Tag::FootnoteDefinition(_) => return HtmlError::err("footnote: not implemented"),
-            Tag::MetadataBlock { .. } => {
-                let _ = self.children(tag);
+            // the definitions are kept by `next`, so they are not rendered in the flow
+            Tag::FootnoteDefinition(_) => {
+                self.store_footnote_definition(tag);
+                view! { <span></span> }.into_any()
+            }
+            Tag::MetadataBlock(kind) => {
+                let events = self.collect_children(&tag);
+                if let Some(f) = &self.context.on_metadata {
+                    let kind = match kind {
+                        MetadataBlockKind::PlusesStyle => MetadataKind::Toml,
+                        MetadataBlockKind::YamlStyle => MetadataKind::Yaml,
+                    };
+                    f.call(Metadata {
+                        kind,
+                        content: plain_text(&events),
+                    })
+                }
                 view! { <div></div>}.into_any()
             }
+            // the tags added by a newer version of the parser, skipped with their content
+            #[allow(unreachable_patterns)]
+            _ => {
+                self.collect_children(&tag);
+                render_unknown(&format!("{tag:?}"))
+            }
         })
     }
 }
 
+/// `render_unknown(description)` renders an event that is not supported, described by
+/// `description`. With the `debug` feature, it is shown as a placeholder,
+/// and like all the events it is reported to [`EventInfo`](crate::debug::EventInfo).
+/// Otherwise it is not shown
+fn render_unknown(description: &str) -> Html {
+    #[cfg(feature = "debug")]
+    {
+        view! { <span class="unknown-event">{format!("unsupported: {description}")}</span> }
+            .into_any()
+    }
+    #[cfg(not(feature = "debug"))]
+    {
+        let _ = description;
+        view! { <span></span> }.into_any()
+    }
+}
+
 fn render_tasklist_marker(context: &RenderContext, m: bool, position: Range<usize>) -> Html {
     let onclick = context.onclick.clone();
+    let ancestors = context.ancestors.borrow().clone();
+    let index = context.task_count.get();
+    context.task_count.set(index + 1);
+    let label = context.task_label.take().filter(|l| !l.is_empty());
+    let label = label.unwrap_or_else(|| format!("task {}", index + 1));
     let callback = move |e: MouseEvent| {
         e.prevent_default();
         e.stop_propagation();
         let click_event = MarkdownMouseEvent {
             mouse_event: e,
             position: position.clone(),
+            tag: MarkdownTag::TaskListMarker { checked: m, index },
+            ancestors: ancestors.clone(),
         };
         onclick.call(click_event)
     };
+    if context.read_only_tasks {
+        // disabled inputs do not receive clicks, so the wrapper receives them instead
+        return view! {
+            <span class="task-list-marker" on:click=callback>
+                <input type="checkbox" checked=m disabled=true aria-label=label
+                    style="pointer-events: none">
+                    </input>
+            </span>
+        }
+        .into_any();
+    }
     view! {
-         <input type="checkbox" checked=m on:click=callback>
+         <input type="checkbox" checked=m aria-label=label on:click=callback>
             </input>
     }
     .into_any()
 }
 
+fn render_footnote_reference(context: &RenderContext, label: &str) -> Html {
+    let defined = context.footnote_labels.as_ref().map_or(true, |l| l.contains(label));
+    if !defined {
+        // like on github, a reference to a missing footnote is just text
+        return view! { <span>{format!("[^{label}]")}</span> }.into_any();
+    }
+    let (n, k) = context.footnotes.borrow_mut().reference(label);
+    let id = footnote_reference_id(n, k);
+    view! {
+        <sup class="footnote-reference">
+            <a href=format!("#fn-{n}") id=id title=context.footnote_texts.get(label).cloned()>
+                {n}
+            </a>
+        </sup>
+    }
+    .into_any()
+}
+
+/// `render_footnotes(context)` renders the list of all the footnotes
+/// referenced in the document, numbered in reference order.
+/// Returns `None` if there is no footnote.
+pub fn render_footnotes(context: &RenderContext) -> Option<Html> {
+    let mut footnotes = context.footnotes.borrow_mut();
+    if footnotes.labels.is_empty() {
+        return None;
+    }
+
+    let labels = std::mem::take(&mut footnotes.labels);
+    let counts = std::mem::take(&mut footnotes.reference_counts);
+    let items = labels
+        .iter()
+        .zip(counts)
+        .enumerate()
+        .map(|(i, (label, count))| {
+            let n = i + 1;
+            let content = footnotes.definitions.remove(label);
+            // one link back to each reference, numbered if there are several
+            let backrefs = (1..=count)
+                .map(|k| {
+                    let number = (count > 1).then(|| view! { <sup>{k}</sup> });
+                    let href = format!("#{}", footnote_reference_id(n, k));
+                    view! {
+                        <a href=href class="footnote-backref">
+                            "↩"{number}
+                        </a>
+                    }
+                })
+                .collect_view();
+            view! {
+                <li id=format!("fn-{n}")>
+                    {content}
+                    {backrefs}
+                </li>
+            }
+        })
+        .collect_view();
+
+    Some(
+        view! {
+            <section class="footnotes">
+                <hr/>
+                <ol>{items}</ol>
+            </section>
+        }
+        .into_any(),
+    )
+}
+
 fn render_rule(context: &RenderContext, range: Range<usize>) -> Html {
-    let callback = make_callback(context, range);
+    let callback = make_callback(context, MarkdownTag::Rule, range);
     view! { <hr on:click=callback/>}.into_any()
 }
 
+/// `is_block(tag)` tells wether `tag` is rendered as a block element
+fn is_block(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Paragraph
+            | Tag::Heading { .. }
+            | Tag::BlockQuote
+            | Tag::CodeBlock(_)
+            | Tag::List(_)
+            | Tag::Item
+            | Tag::Table(_)
+    )
+}
+
+/// `bullet_class(bullet)` gives the class of an unordered list written with the marker `bullet`,
+/// named after the `list-style-type` it is usually shown with
+fn bullet_class(bullet: char) -> &'static str {
+    match bullet {
+        '*' => "list-circle",
+        '+' => "list-square",
+        _ => "list-disc",
+    }
+}
+
+/// `element_kind(tag)` gives the kind of the element rendered for `tag`, if it has one
+fn element_kind(tag: &Tag) -> Option<ElementKind> {
+    match tag {
+        Tag::Paragraph => Some(ElementKind::Paragraph),
+        Tag::Heading { .. } => Some(ElementKind::Heading),
+        Tag::BlockQuote => Some(ElementKind::BlockQuote),
+        Tag::CodeBlock(_) => Some(ElementKind::CodeBlock),
+        Tag::List(_) => Some(ElementKind::List),
+        Tag::Item => Some(ElementKind::ListItem),
+        Tag::Table(_) => Some(ElementKind::Table),
+        Tag::Link { .. } => Some(ElementKind::Link),
+        Tag::Image { .. } => Some(ElementKind::Image),
+        _ => None,
+    }
+}
+
+/// `out_of_source(range)` is the error of an event at `range`, that is not in the source
+fn out_of_source(range: &Range<usize>) -> HtmlError {
+    HtmlError::MalformedEvents(format!("the range {range:?} is not in the source"))
+}
+
+/// `with_source_pos(html, range)` adds the position `range` in the markdown source
+/// to the element `html`, as `data-source-start` and `data-source-end` byte offsets
+fn with_source_pos(html: Html, range: &Range<usize>) -> Html {
+    html.attr("data-source-start", range.start.to_string())
+        .attr("data-source-end", range.end.to_string())
+}
+
+/// `inline_tag_html(context, tag)` gives the opening and closing html tags
+/// of a markdown element inside inline html, see [`RenderContext::presentational_tags`]
+fn inline_tag_html(context: &RenderContext, tag: &Tag) -> (String, &'static str) {
+    let presentational = context.presentational_tags;
+    match tag {
+        Tag::Emphasis if presentational => ("<i>".into(), "</i>"),
+        Tag::Emphasis => ("<em>".into(), "</em>"),
+        Tag::Strong if presentational => ("<b>".into(), "</b>"),
+        Tag::Strong => ("<strong>".into(), "</strong>"),
+        Tag::Strikethrough if presentational => ("<s>".into(), "</s>"),
+        Tag::Strikethrough => ("<del>".into(), "</del>"),
+        Tag::Link {
+            dest_url, title, ..
+        } => {
+            let title = match title.is_empty() {
+                true => String::new(),
+                false => format!(" title=\"{}\"", escape_html(title)),
+            };
+            let href = match context.sanitize_html && is_unsafe_url(dest_url) {
+                true => String::new(),
+                false => format!(" href=\"{}\"", escape_html(dest_url)),
+            };
+            (format!("<a{href}{title}>"), "</a>")
+        }
+        _ => (String::new(), ""),
+    }
+}
+
+fn render_inline_html(context: &RenderContext, s: &str, range: Range<usize>) -> Html {
+    let callback = make_callback(context, MarkdownTag::Html, range);
+    let html = match context.sanitize_html {
+        true => sanitize(s),
+        false => s.to_string(),
+    };
+    view! {
+        <span on:click=callback inner_html=html>
+        </span>
+    }
+    .into_any()
+}
+
 fn render_html(context: &RenderContext, s: &str, range: Range<usize>) -> Html {
-    let callback = make_callback(context, range);
+    let callback = make_callback(context, MarkdownTag::Html, range);
+    let html = match context.sanitize_html {
+        true => sanitize(s),
+        false => s.to_string(),
+    };
     view! {
-        <div on:click=callback inner_html={s.to_string()}>
+        <div on:click=callback inner_html=html>
         </div>
     }
     .into_any()
 }
 
+/// `sanitize(html)` removes everything that could run scripts from `html`
+/// (`<script>` tags, event handlers, `javascript:` urls, ...)
+/// but keeps the common formatting tags, and the `class` and `id` attributes
+fn sanitize(html: &str) -> String {
+    ammonia::Builder::default()
+        .add_generic_attributes(&["class", "id"])
+        .clean(html)
+        .to_string()
+}
+
 fn render_code(context: &RenderContext, s: &str, range: Range<usize>) -> Html {
-    let callback = make_callback(context, range);
-    view! { <code on:click=callback>{s.to_string()}</code>}.into_any()
+    let callback = make_callback(context, MarkdownTag::Code, range);
+    view! {
+        <code class=context.class(ElementKind::Code, None) on:click=callback>
+            {s.to_string()}
+        </code>
+    }
+    .into_any()
 }
 
-fn render_text(context: &RenderContext, s: &str, range: Range<usize>) -> Html {
-    let callback = make_callback(context, range);
+/// `render_text(context, s, escaped, range)` renders the text `s`,
+/// with the inline extensions enabled in `context`.
+/// If `escaped` is true, the first character of `s` can not start an extension
+fn render_text(context: &RenderContext, s: &str, escaped: bool, range: Range<usize>) -> Html {
+    let callback = make_callback(context, MarkdownTag::Text, range);
+
+    let mut spans = vec![InlineSpan::Text(s.to_string())];
+    if escaped {
+        let first = s.chars().next().map_or(0, char::len_utf8);
+        spans = vec![
+            InlineSpan::Text(s[..first].to_string()),
+            InlineSpan::Text(s[first..].to_string()),
+        ]
+    }
+
+    if let Some(emojis) = &context.emojis {
+        for span in &mut spans {
+            if let InlineSpan::Text(text) = span {
+                *text = replace_shortcodes(text, emojis);
+            }
+        }
+    }
+
+    if context.highlight_syntax {
+        spans = split_marks(spans);
+    }
+
+    if context.sub_superscript {
+        spans = split_delimited(spans, '^', InlineSpan::Superscript);
+        spans = split_delimited(spans, '~', InlineSpan::Subscript);
+    }
+
     view! {
         <span on:click=callback>
-            {s.to_string()}
+            {spans.into_iter().collect_view()}
         </span>
     }
     .into_any()
 }
 
+/// `render_autolinks(context, s, escaped, range)` renders the text `s`,
+/// with its bare urls and email addresses rendered as links
+fn render_autolinks(
+    context: &RenderContext,
+    s: &str,
+    escaped: bool,
+    range: Range<usize>,
+) -> Result<Html, HtmlError> {
+    let urls = find_autolinks(s);
+    // the emails inside urls, like in `https://user@example.com`, are part of the url
+    let emails = find_emails(s)
+        .into_iter()
+        .filter(|e| !urls.iter().any(|u| u.start < e.end && e.start < u.end));
+    let mut links: Vec<_> = urls.iter().cloned().map(|r| (r, false)).collect();
+    links.extend(emails.map(|r| (r, true)));
+    links.sort_by_key(|(r, _)| r.start);
+    if links.is_empty() {
+        return Ok(render_text(context, s, escaped, range));
+    }
+
+    // the positions in the source are only known if the text is written as is
+    let source_range = |r: Range<usize>| match range.len() == s.len() {
+        true => range.start + r.start..range.start + r.end,
+        false => range.clone(),
+    };
+
+    let mut parts = Vec::new();
+    let mut end = 0;
+    for (link, email) in links {
+        if link.start > end {
+            let text = &s[end..link.start];
+            let escaped = escaped && end == 0;
+            parts.push(render_text(context, text, escaped, source_range(end..link.start)));
+        }
+        let text = &s[link.clone()];
+        let url = match (email, text.starts_with("www.")) {
+            (true, _) => format!("mailto:{text}"),
+            (false, true) => format!("http://{text}"),
+            (false, false) => text.to_string(),
+        };
+        let description = LinkDescription {
+            url,
+            content: render_text(context, text, false, source_range(link.clone())).into_view(),
+            text: text.to_string(),
+            title: String::new(),
+            link_type: if email { LinkType::Email } else { LinkType::Autolink },
+            broken: false,
+            image: false,
+            size: ImageSize::default(),
+        };
+        parts.push(render_link(context, description)?);
+        end = link.end;
+    }
+    if end < s.len() {
+        parts.push(render_text(context, &s[end..], false, source_range(end..s.len())));
+    }
+
+    Ok(view! { <span>{parts}</span> }.into_any())
+}
+
+fn render_math(context: &RenderContext, content: &str, range: Range<usize>) -> Html {
+    let callback = make_callback(context, MarkdownTag::Math, range);
+    view! {
+        <div class="math">
+            <span role="math" on:click=callback aria-label=content.to_string()>
+                {content.to_string()}
+            </span>
+        </div>
+    }
+    .into_any()
+}
+
 fn render_code_block(
     context: &RenderContext,
     string_content: Option<String>,
     k: &CodeBlockKind,
+    open: bool,
     range: Range<usize>,
 ) -> Html {
-    let content = match string_content {
-        Some(x) => x,
+    // a fence that was just opened has no content yet
+    let content = string_content.unwrap_or_default();
+
+    let mut info = match k {
+        CodeBlockKind::Fenced(x) => parse_info_string(x),
+        CodeBlockKind::Indented => CodeInfo::default(),
+    };
+    // the tag is the one of the source, like the ancestors of the content,
+    // so it is made before the default language is applied
+    let callback = make_callback(context, MarkdownTag::CodeBlock(info.lang.clone()), range);
+
+    if info.lang.is_empty() {
+        info.lang = context.default_code_language.clone().unwrap_or_default();
+    }
+
+    if let Some(f) = &context.render_code_block {
+        let description = CodeBlockInfo {
+            lang: info.lang.clone(),
+            content: content.clone(),
+        };
+        if let Some(html) = f.call(description) {
+            return html;
+        }
+    }
+
+    // an unfinished diagram can not be drawn
+    if context.diagram_languages.contains(&info.lang) && !open {
+        context.has_diagrams.set(true);
+        // the source is inserted as text, so the browser gives it back
+        // unescaped to the diagram library
+        return view! {
+            <div class=format!("diagram {}", info.lang) on:click=callback>
+                {content}
+            </div>
+        }
+        .into_any();
+    }
+
+    // unfinished code is shown as it is, since its highlighting would change at each token,
+    // and highlighted once its fence is closed
+    let highlighted = match open {
+        true => None,
+        false => highlight_code(context, &content, &info),
+    };
+    // in both cases the `<pre>` gets a `language-{lang}` class,
+    // so that client side tools can find the code blocks of a language
+    let lang = (!info.lang.is_empty()).then_some(info.lang.clone());
+    let lang_class = lang.as_ref().map(|x| format!("language-{x}"));
+    let block = match highlighted {
+        // the language is kept so that client side highlighters can take over
         None => {
-            return view! {
-                <code></code>
+            let class = match (lang_class, open) {
+                (Some(lang), true) => Some(format!("{lang} code-block-open")),
+                (lang, open) => lang.or(open.then(|| "code-block-open".to_string())),
+            };
+            view! {
+                <pre class=context.class(ElementKind::CodeBlock, class) on:click=callback>
+                    <code class=lang.as_ref().map(|x| format!("language-{x}")) data-lang=lang>
+                        {content.clone()}
+                    </code>
+                </pre>
+            }
+            .into_any()
+        }
+        Some(x) => {
+            let x = match &lang_class {
+                Some(class) => with_pre_class(&x, class),
+                None => x,
+            };
+            view! {
+                <div class=context.class(ElementKind::CodeBlock, None) data-lang=lang
+                    on:click=callback inner_html=x>
+                </div>
             }
             .into_any()
         }
     };
 
-    let callback = make_callback(context, range);
-
-    match highlight_code(context, &content, &k) {
-        None => view! {
-        <code on:click=callback>
-            <pre inner_html=content.to_string()></pre>
-        </code>
+    let block = match context.copy_button {
+        true => view! {
+            <div class="code-block" style="position: relative">
+                {block}
+                {render_copy_button(content)}
+            </div>
         }
         .into_any(),
-        Some(x) => view! {
-            <div on:click=callback inner_html=x>
-                </div>
+        false => block,
+    };
+
+    match info.title {
+        Some(title) => view! {
+            <div class="code-block-with-title">
+                <div class="code-title">{title}</div>
+                {block}
+            </div>
         }
         .into_any(),
+        None => block,
     }
 }
 
-/// `highlight_code(content, ss, ts)` render the content `content`
-/// with syntax highlighting
-fn highlight_code(context: &RenderContext, content: &str, kind: &CodeBlockKind) -> Option<String> {
-    let lang = match kind {
-        CodeBlockKind::Fenced(x) => x,
-        CodeBlockKind::Indented => return None,
+/// `render_copy_button(content)` renders a button that copies
+/// `content` to the clipboard when clicked
+fn render_copy_button(content: String) -> Html {
+    let (copied, set_copied) = create_signal(false);
+    let copy = move |e: MouseEvent| {
+        e.stop_propagation();
+        if clipboard_write_text(&content).is_ok() {
+            set_copied.set(true);
+            set_timeout(move || set_copied.set(false), Duration::from_secs(2));
+        }
     };
-    Some(
-        syntect::html::highlighted_html_for_string(
+
+    view! {
+        <button class="copy-button"
+                style="position: absolute; top: 0; right: 0"
+                on:click=copy>
+            {move || if copied.get() {"Copied!"} else {"Copy"}}
+        </button>
+    }
+    .into_any()
+}
+
+/// `highlight_code(context, content, info)` render the content `content`
+/// with syntax highlighting, using the language from `info`
+fn highlight_code(context: &RenderContext, content: &str, info: &CodeInfo) -> Option<String> {
+    if context.max_highlight_bytes.is_some_and(|max| content.len() > max) {
+        return None;
+    }
+    if let Some(highlighter) = &context.highlighter {
+        return highlighter.highlight(&info.lang, content);
+    }
+    let syntax = context.syntax_set.find_syntax_by_token(&info.lang)?;
+    let theme = context.theme_for(&info.lang);
+
+    // the lines of diffs get their own classes
+    let by_line =
+        context.line_numbers || !info.highlighted_lines.is_empty() || info.lang == "diff";
+
+    match (context.css_classes, by_line) {
+        (false, false) => syntect::html::highlighted_html_for_string(
             content,
             &context.syntax_set,
-            context.syntax_set.find_syntax_by_token(lang)?,
-            &context.theme,
+            syntax,
+            theme,
         )
-        .ok()?,
-    )
+        .ok(),
+        (false, true) => {
+            let lines = highlight_lines(context, content, syntax, theme)?;
+            Some(lines_html(context, content, &lines, info))
+        }
+        (true, false) => Some(format!(
+            "<pre class=\"code\">{}</pre>",
+            classed_html(context, content, syntax)?
+        )),
+        (true, true) => {
+            let lines = split_html_lines(&classed_html(context, content, syntax)?);
+            Some(lines_html(context, content, &lines, info))
+        }
+    }
 }
 
-/// `render_header(d, s)` returns the html corresponding to
-/// the string `s` inside a html header with depth `d`
-fn render_heading<I: IntoView>(level: HeadingLevel, content: I) -> Html {
+/// `with_pre_class(html, class)` adds `class` to the first `<pre>` element of `html`.
+/// The class comes from the info string, so it is escaped
+fn with_pre_class(html: &str, class: &str) -> String {
+    let Some(start) = html.find("<pre") else {
+        return html.to_string();
+    };
+    let class = escape_html(class);
+    let tag_end = html[start..].find('>').map_or(html.len(), |i| start + i);
+    match html[start..tag_end].find("class=\"") {
+        Some(i) => {
+            let at = start + i + "class=\"".len();
+            format!("{}{class} {}", &html[..at], &html[at..])
+        }
+        None => {
+            let at = start + "<pre".len();
+            format!("{} class=\"{class}\"{}", &html[..at], &html[at..])
+        }
+    }
+}
+
+/// `classed_html(context, content, syntax)` highlights `content`
+/// with css classes instead of inline styles, like `source rust keyword`.
+/// The colors come from a stylesheet, see [`theme_css`]
+fn classed_html(
+    context: &RenderContext,
+    content: &str,
+    syntax: &SyntaxReference,
+) -> Option<String> {
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(
+        syntax,
+        &context.syntax_set,
+        ClassStyle::Spaced,
+    );
+    for line in LinesWithEndings::from(content) {
+        generator
+            .parse_html_for_line_which_includes_newline(line)
+            .ok()?;
+    }
+    Some(generator.finalize())
+}
+
+/// `theme_css(theme)` returns the css stylesheet of a syntax highlighting theme,
+/// to use with the `css_classes` mode.
+/// Returns `None` if the theme does not exist.
+pub fn theme_css(theme: ThemeSource) -> Option<String> {
+    let theme = match theme {
+        ThemeSource::Custom(theme) => theme,
+        ThemeSource::Named(name) => DEFAULT_THEME_SET.with(|set| set.themes.get(&name).cloned())?,
+    };
+    css_for_theme_with_class_style(&theme, ClassStyle::Spaced).ok()
+}
+
+/// `highlight_lines(context, content, syntax, theme)` highlights `content`
+/// and returns the html of each line, without the trailing newlines
+fn highlight_lines(
+    context: &RenderContext,
+    content: &str,
+    syntax: &SyntaxReference,
+    theme: &Theme,
+) -> Option<Vec<String>> {
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    LinesWithEndings::from(content)
+        .map(|line| {
+            let mut regions = highlighter.highlight_line(line, &context.syntax_set).ok()?;
+            if let Some((_, text)) = regions.last_mut() {
+                *text = text.trim_end_matches('\n');
+            }
+            styled_line_to_highlighted_html(&regions, IncludeBackground::No).ok()
+        })
+        .collect()
+}
+
+/// `css_color(color)` converts a syntect color to a css color
+fn css_color(c: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}{:02x}", c.r, c.g, c.b, c.a)
+}
+
+/// `lines_html(context, content, lines, info)` renders the already highlighted lines
+/// of `content` in a `<pre>`, each line in its own element.
+/// The lines selected in `info` get the `highlighted` class,
+/// the lines of diffs get a class telling if they are added or removed,
+/// and if enabled a line number is added in front of each line.
+/// The numbers can not be selected, so they are not copied with the code
+fn lines_html(context: &RenderContext, content: &str, lines: &[String], info: &CodeInfo) -> String {
+    // with css classes, the colors come from the stylesheet
+    let settings = &context.theme_for(&info.lang).settings;
+    let background = settings
+        .background
+        .filter(|_| !context.css_classes)
+        .map(|c| format!("background-color:{};", css_color(c)))
+        .unwrap_or_default();
+    let highlight_background = match settings.line_highlight {
+        _ if context.css_classes => String::new(),
+        Some(c) => format!("background-color: {}", css_color(c)),
+        None => "background-color: rgba(255, 255, 0, 0.2)".to_string(),
+    };
+
+    let mut html = format!("<pre class=\"code\" style=\"{background}\">");
+    for (i, (line, source)) in lines.iter().zip(content.lines()).enumerate() {
+        let n = i + 1;
+        let (mut class, mut style) = match info.is_highlighted(n) {
+            true => (
+                "code-line highlighted".to_string(),
+                format!("display: block; {highlight_background}"),
+            ),
+            false => ("code-line".to_string(), "display: block".to_string()),
+        };
+        if info.lang == "diff" {
+            let kind = diff_line_kind(source);
+            class.push_str(&format!(" diff-{kind}"));
+            match kind {
+                _ if context.css_classes => (),
+                "added" => style.push_str("; background-color: rgba(0, 255, 0, 0.15)"),
+                "removed" => style.push_str("; background-color: rgba(255, 0, 0, 0.15)"),
+                _ => (),
+            }
+        }
+        html.push_str(&format!("<span class=\"{class}\" style=\"{style}\">"));
+        if context.line_numbers {
+            html.push_str(&format!(
+                "<span class=\"line-number\" \
+                       style=\"display: inline-block; min-width: 2em; padding-right: 1em; \
+                              text-align: right; user-select: none; opacity: 0.5\">{n}</span>"
+            ));
+        }
+        html.push_str(line);
+        html.push_str("</span>");
+    }
+    html.push_str("</pre>");
+    html
+}
+
+/// `diff_line_kind(line)` tells what the line `line` of a diff is:
+/// `added`, `removed`, `hunk` (like `@@ -1,2 +1,3 @@`), `header` (the file names),
+/// or `context` for the unchanged lines
+fn diff_line_kind(line: &str) -> &'static str {
+    match line {
+        _ if line.starts_with("+++ ") || line.starts_with("--- ") => "header",
+        _ if line.starts_with('+') => "added",
+        _ if line.starts_with('-') => "removed",
+        _ if line.starts_with("@@") => "hunk",
+        _ => "context",
+    }
+}
+
+/// `render_header(d, id, s)` returns the html corresponding to
+/// the string `s` inside a html header with depth `d` and an optional `id`
+fn render_heading<I: IntoView>(
+    level: HeadingLevel,
+    id: Option<String>,
+    class: Option<String>,
+    content: I,
+) -> Html {
     use HeadingLevel::*;
     match level {
-        H1 => view! {<h1>{content}</h1>}.into_any(),
-        H2 => view! {<h2>{content}</h2>}.into_any(),
-        H3 => view! {<h3>{content}</h3>}.into_any(),
-        H4 => view! {<h4>{content}</h4>}.into_any(),
-        H5 => view! {<h5>{content}</h5>}.into_any(),
-        H6 => view! {<h6>{content}</h6>}.into_any(),
+        H1 => view! {<h1 id=id class=class>{content}</h1>}.into_any(),
+        H2 => view! {<h2 id=id class=class>{content}</h2>}.into_any(),
+        H3 => view! {<h3 id=id class=class>{content}</h3>}.into_any(),
+        H4 => view! {<h4 id=id class=class>{content}</h4>}.into_any(),
+        H5 => view! {<h5 id=id class=class>{content}</h5>}.into_any(),
+        H6 => view! {<h6 id=id class=class>{content}</h6>}.into_any(),
     }
 }
 
 fn render_link(context: &RenderContext, link: LinkDescription) -> Result<Html, HtmlError> {
     match (&context.render_links, link.image) {
         (Some(f), _) => Ok(f.call(link)),
-        (None, false) => Ok(view! {
-            <a href={link.url}>
-                {link.content}
-            </a>
+        (None, false) => {
+            let on_link_click = context.on_link_click.clone();
+            let url = link.url.clone();
+            let broken = link.broken;
+            let callback = move |e: MouseEvent| {
+                if let Some(f) = &on_link_click {
+                    let host = window().location().host().unwrap_or_default();
+                    f.call(LinkClickEvent {
+                        mouse_event: e,
+                        internal: is_internal_url(&url, &host),
+                        url: url.clone(),
+                        broken,
+                    })
+                }
+            };
+            if context.obfuscate_emails && link.url.starts_with("mailto:") {
+                return Ok(render_obfuscated_email(&link.url, &link.text, callback));
+            }
+
+            // the page is not known during server side rendering,
+            // so only the configured host is considered internal
+            let host = context.site_host.as_deref().unwrap_or("");
+            let absolute = link.url.contains("://") || link.url.starts_with("//");
+            let external = absolute && !is_internal_url(&link.url, host);
+            let new_tab = context.external_links_new_tab && external;
+            // the link is kept, but can not be followed
+            let href = match context.sanitize_html && is_unsafe_url(&link.url) {
+                true => None,
+                false => Some(link.url),
+            };
+            let rel = [
+                external.then(|| context.link_rel.as_deref()).flatten(),
+                new_tab.then_some("noopener noreferrer"),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+            let title = (!link.title.is_empty()).then_some(link.title);
+            Ok(view! {
+                <a href=href
+                   title=title
+                   class=context.class(ElementKind::Link, broken.then(|| "wikilink-broken".into()))
+                   target=new_tab.then_some("_blank")
+                   rel=(!rel.is_empty()).then_some(rel)
+                   on:click=callback>
+                    {link.content}
+                </a>
+            }
+            .into_any())
         }
-        .into_any()),
-        (None, true) => Ok(view! {
-            <img src={link.url} alt=link.title/>
+        (None, true) => {
+            let title = (!link.title.is_empty()).then_some(link.title);
+            let lazy = !context.eager_images;
+            // sizes in pixels are attributes, so the space is reserved before the image loads
+            let is_pixels = |x: &String| x.chars().all(|c| c.is_ascii_digit());
+            let ImageSize { width, height } = link.size;
+            let style = [("width", &width), ("height", &height)]
+                .into_iter()
+                .filter_map(|(name, x)| match x {
+                    Some(x) if !is_pixels(x) => Some(format!("{name}: {x}")),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            let on_image_click = context.on_image_click.clone();
+            let (url, alt) = (link.url.clone(), link.text.clone());
+            let callback = move |e: MouseEvent| {
+                if let Some(f) = &on_image_click {
+                    f.call(ImageClickEvent {
+                        mouse_event: e,
+                        url: url.clone(),
+                        alt: alt.clone(),
+                    })
+                }
+            };
+            Ok(view! {
+                <img src={link.url}
+                     on:click=callback
+                     class=context.class(ElementKind::Image, None)
+                     alt=link.text
+                     title=title
+                     width=width.filter(is_pixels)
+                     height=height.filter(is_pixels)
+                     style=(!style.is_empty()).then_some(style)
+                     loading=lazy.then_some("lazy")
+                     decoding=lazy.then_some("async")
+                />
+            }
+            .into_any())
         }
-        .into_any()),
     }
 }
 
+/// `render_obfuscated_email(url, text, callback)` renders a link to the email `url`,
+/// with its url and text written as html entities, so that they are not
+/// in clear text in the html sent by the server
+fn render_obfuscated_email(url: &str, text: &str, callback: impl Fn(MouseEvent) + 'static) -> Html {
+    let encode = |s: &str| s.chars().map(|c| format!("&#{};", c as u32)).collect::<String>();
+    let html = format!("<a href=\"{}\">{}</a>", encode(url), encode(text));
+    view! { <span class="email" on:click=callback inner_html=html></span> }.into_any()
+}
+
 /// `align_string(align)` gives the css string
 /// that is used to align text according to `align`
-fn align_string(align: &Alignment) -> &'static str {
+fn align_string(align: &Alignment) -> Option<&'static str> {
     match align {
-        Alignment::Left => "text-align: left",
-        Alignment::Right => "text-align: right",
-        Alignment::Center => "text-align: center",
-        Alignment::None => "",
+        Alignment::Left => Some("text-align: left"),
+        Alignment::Right => Some("text-align: right"),
+        Alignment::Center => Some("text-align: center"),
+        Alignment::None => None,
     }
 }
 
-/// `render_cell(cell, align, context)` renders cell as html,
-/// and use `align` to
-fn render_cell<'a>(content: View, align: &'a Alignment) -> Html {
-    view! {
-        <td style={align_string(align)}>
-            {content}
-        </td>
+/// `align_class(align)` gives the class
+/// that is used to align text according to `align`
+fn align_class(align: &Alignment) -> Option<&'static str> {
+    match align {
+        Alignment::Left => Some("md-align-left"),
+        Alignment::Right => Some("md-align-right"),
+        Alignment::Center => Some("md-align-center"),
+        Alignment::None => None,
+    }
+}
+
+/// `render_cell(cell, align, classes, head)` renders cell as html,
+/// and use `align` to align its content,
+/// with a class if `classes` is set or with an inline style otherwise.
+/// Cells of the head of the table (when `head` is set) are `<th scope="col">`
+fn render_cell<'a>(content: View, align: &'a Alignment, classes: bool, head: bool) -> Html {
+    let (class, style) = match classes {
+        true => (align_class(align), None),
+        false => (None, align_string(align)),
+    };
+    match head {
+        true => view! {
+            <th scope="col" class=class style=style>
+                {content}
+            </th>
+        }
+        .into_any(),
+        false => view! {
+            <td class=class style=style>
+                {content}
+            </td>
+        }
+        .into_any(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pre_class_is_added_and_escaped() {
+        let html = "<pre style=\"color: red\">code</pre>";
+        assert_eq!(
+            with_pre_class(html, "language-rust"),
+            "<pre class=\"language-rust\" style=\"color: red\">code</pre>"
+        );
+        assert_eq!(
+            with_pre_class("<pre class=\"code\">code</pre>", "language-rust"),
+            "<pre class=\"language-rust code\">code</pre>"
+        );
+        let injected = with_pre_class(html, "language-x\"onmouseover=\"alert(1)");
+        assert!(!injected.contains("\"onmouseover"));
     }
-    .into_any()
 }
@@ -0,0 +1,169 @@
+use pulldown_cmark_wikilink::{Event, Options, ParserOffsetIter, Tag, TagEnd};
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// `extract_plain_text(src, options, wikilinks)` parses the markdown `src`,
+/// with wikilinks if `wikilinks` is set like the prop of the same name,
+/// and returns its text content, without any markup.
+/// Blocks end with a line break, link and wikilink texts are kept,
+/// maths, raw html and metadata blocks are removed.
+///
+/// This does not need a reactive runtime, so it can be used anywhere,
+/// for example to build a search index on the server.
+pub fn extract_plain_text(src: &str, options: Options, wikilinks: bool) -> String {
+    let events: Vec<_> = ParserOffsetIter::new_ext(src, options, wikilinks)
+        .map(|(event, _)| event)
+        .collect();
+    events_text(events.iter(), true)
+}
+
+/// statistics about the text of a document
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocStats {
+    /// the number of words
+    pub words: usize,
+
+    /// the number of characters, without whitespace
+    pub characters: usize,
+
+    /// the estimated time needed to read the document
+    pub reading_time: Duration,
+}
+
+/// `doc_stats(events, words_per_minute, include_code_blocks)` computes
+/// the statistics of the text of `events`, read at `words_per_minute`
+pub fn doc_stats<'a, 'b>(
+    events: impl Iterator<Item = &'b Event<'a>>,
+    words_per_minute: u32,
+    include_code_blocks: bool,
+) -> DocStats
+where
+    'a: 'b,
+{
+    let text = events_text(events, include_code_blocks);
+    let words = text.split_whitespace().count();
+    let characters = text.chars().filter(|c| !c.is_whitespace()).count();
+    let minutes = words as f64 / words_per_minute.max(1) as f64;
+    DocStats {
+        words,
+        characters,
+        reading_time: Duration::from_secs_f64(minutes * 60.),
+    }
+}
+
+/// `footnote_texts(events)` gives the text content of each footnote definition
+/// of `events`, by label
+pub fn footnote_texts<'a, 'b>(
+    events: impl Iterator<Item = &'b Event<'a>>,
+) -> HashMap<String, String>
+where
+    'a: 'b,
+{
+    let mut texts = HashMap::new();
+    let mut definition: Option<(String, Vec<&Event>)> = None;
+    for event in events {
+        match (event, &mut definition) {
+            (Event::Start(Tag::FootnoteDefinition(label)), _) => {
+                definition = Some((label.to_string(), Vec::new()))
+            }
+            (Event::End(TagEnd::FootnoteDefinition), Some(_)) => {
+                let (label, events) = definition.take().unwrap();
+                texts.insert(label, events_text(events.into_iter(), true));
+            }
+            (event, Some((_, events))) => events.push(event),
+            _ => (),
+        }
+    }
+    texts
+}
+
+/// `footnote_labels(events)` gives the labels of the footnotes defined in `events`,
+/// and the labels of the footnotes referenced in `events`
+pub fn footnote_labels<'a, 'b>(
+    events: impl Iterator<Item = &'b Event<'a>>,
+) -> (HashSet<String>, HashSet<String>)
+where
+    'a: 'b,
+{
+    let mut defined = HashSet::new();
+    let mut referenced = HashSet::new();
+    for event in events {
+        match event {
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                defined.insert(label.to_string());
+            }
+            Event::FootnoteReference(label) => {
+                referenced.insert(label.to_string());
+            }
+            _ => (),
+        }
+    }
+    (defined, referenced)
+}
+
+/// `events_text(events, include_code_blocks)` gives the text content of `events`,
+/// see [`extract_plain_text`]
+fn events_text<'a, 'b>(
+    events: impl Iterator<Item = &'b Event<'a>>,
+    include_code_blocks: bool,
+) -> String
+where
+    'a: 'b,
+{
+    let mut text = String::new();
+    let mut in_metadata = false;
+    let mut in_code_block = false;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::MetadataBlock(_)) => in_metadata = true,
+            Event::End(TagEnd::MetadataBlock(_)) => in_metadata = false,
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => {
+                in_code_block = false;
+                if !text.ends_with('\n') {
+                    text.push('\n')
+                }
+            }
+            _ if in_metadata => (),
+            _ if in_code_block && !include_code_blocks => (),
+            Event::Text(s) | Event::Code(s) => text.push_str(s),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            Event::End(TagEnd::TableCell) => text.push(' '),
+            Event::End(end) if is_block(*end) && !text.ends_with('\n') => text.push('\n'),
+            _ => (),
+        }
+    }
+
+    text.trim_end().to_string()
+}
+
+/// `is_block(end)` tells wether `end` closes a block element,
+/// after which the text should go on a new line
+fn is_block(end: TagEnd) -> bool {
+    matches!(
+        end,
+        TagEnd::Paragraph
+            | TagEnd::Heading(_)
+            | TagEnd::BlockQuote
+            | TagEnd::CodeBlock
+            | TagEnd::Item
+            | TagEnd::TableHead
+            | TagEnd::TableRow
+            | TagEnd::FootnoteDefinition
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wikilinks_are_only_parsed_when_enabled() {
+        let text = extract_plain_text("see [[Page]]", Options::empty(), true);
+        assert_eq!(text.trim_end(), "see Page");
+        let text = extract_plain_text("see [[Page]]", Options::empty(), false);
+        assert_eq!(text.trim_end(), "see [[Page]]");
+    }
+}
@@ -1,10 +1,27 @@
-use pulldown_cmark_wikilink::{Tag, TagEnd};
+use pulldown_cmark_wikilink::{Event, HeadingLevel, Tag, TagEnd};
+
+use crate::ImageSize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 
+use wasm_bindgen::prelude::*;
+
+use leptos::{
+    create_effect, create_signal, on_cleanup, set_timeout_with_handle, store_value, MaybeSignal,
+    ReadSignal, SignalGet, SignalGetUntracked, SignalSet, TimeoutHandle,
+};
+use std::time::Duration;
+
+use core::ops::{Range, RangeInclusive};
+
 use leptos::html::{AnyElement, ElementDescriptor, HtmlElement};
 
-pub fn as_closing_tag(t: &Tag) -> TagEnd {
-    match t {
+/// `as_closing_tag(t)` gives the closing tag of the tag `t`, or `None` for the tags
+/// added by a newer version of the parser, whose closing tag is not known
+pub fn as_closing_tag(t: &Tag) -> Option<TagEnd> {
+    let end = match t {
         Tag::Paragraph => TagEnd::Paragraph,
         Tag::Heading { level, .. } => TagEnd::Heading(*level),
         Tag::BlockQuote => TagEnd::BlockQuote,
@@ -22,7 +39,621 @@ pub fn as_closing_tag(t: &Tag) -> TagEnd {
         Tag::Link { .. } => TagEnd::Link,
         Tag::Image { .. } => TagEnd::Image,
         Tag::MetadataBlock(k) => TagEnd::MetadataBlock(*k),
+        #[allow(unreachable_patterns)]
+        _ => return None,
+    };
+    Some(end)
+}
+
+/// `plain_text(events)` concatenates the text inside `events`,
+/// ignoring all the markup
+pub fn plain_text(events: &[(Event, Range<usize>)]) -> String {
+    let mut text = String::new();
+    for (event, _) in events {
+        match event {
+            Event::Text(s) | Event::Code(s) => text.push_str(s),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            _ => (),
+        }
+    }
+    text
+}
+
+/// `slugify(text)` turns `text` into a string usable as an html `id`,
+/// the same way github does for headings:
+/// lowercase, spaces replaced by hyphens and punctuation removed
+pub fn slugify(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[wasm_bindgen]
+extern "C" {
+    /// `mermaid.run()` renders all the mermaid diagrams of the page.
+    /// Fails if the mermaid library is not loaded
+    #[wasm_bindgen(catch, js_namespace = mermaid, js_name = run)]
+    pub fn mermaid_run() -> Result<JsValue, JsValue>;
+
+    /// `navigator.clipboard.writeText(text)` copies `text` to the clipboard.
+    /// The clipboard api is still unstable in web-sys, so it is called directly.
+    /// Fails if it is not available, like on pages that are not served over https
+    #[wasm_bindgen(catch, js_namespace = ["navigator", "clipboard"], js_name = writeText)]
+    pub fn clipboard_write_text(text: &str) -> Result<JsValue, JsValue>;
+}
+
+/// `debounce(source, delay)` returns a signal that follows `source`,
+/// but is only updated once `source` did not change for `delay`.
+/// The pending update is cancelled when the current scope is disposed
+pub fn debounce<T: Clone + 'static>(source: MaybeSignal<T>, delay: Duration) -> ReadSignal<T> {
+    let (value, set_value) = create_signal(source.get_untracked());
+    let pending = store_value(None::<TimeoutHandle>);
+
+    create_effect(move |_| {
+        let new_value = source.get();
+        if let Some(handle) = pending.get_value() {
+            handle.clear()
+        }
+        let handle = set_timeout_with_handle(move || set_value.set(new_value), delay).ok();
+        pending.set_value(handle);
+    });
+
+    on_cleanup(move || {
+        if let Some(handle) = pending.get_value() {
+            handle.clear()
+        }
+    });
+
+    value
+}
+
+/// the kinds of github alerts, with the name used in the markdown marker,
+/// in the class and in the title of the alert
+const ALERT_KINDS: [(&str, &str, &str); 5] = [
+    ("NOTE", "note", "Note"),
+    ("TIP", "tip", "Tip"),
+    ("IMPORTANT", "important", "Important"),
+    ("WARNING", "warning", "Warning"),
+    ("CAUTION", "caution", "Caution"),
+];
+
+/// `take_alert_marker(events)` checks if the events of a blockquote start
+/// with a github alert marker like `[!NOTE]` alone on the first line.
+/// If they do, the marker is removed from `events`, and the class name and title
+/// of the alert are returned
+pub fn take_alert_marker(
+    events: &mut Vec<(Event, Range<usize>)>,
+) -> Option<(&'static str, &'static str)> {
+    if !matches!(events.first(), Some((Event::Start(Tag::Paragraph), _))) {
+        return None;
+    }
+
+    // the marker can be split in several text events
+    let mut first_line = String::new();
+    let mut end = 1;
+    while let Some((Event::Text(s), _)) = events.get(end) {
+        first_line.push_str(s);
+        end += 1;
+    }
+
+    let marker = first_line.trim().strip_prefix("[!")?.strip_suffix(']')?;
+    let (_, class, title) = ALERT_KINDS
+        .into_iter()
+        .find(|(name, _, _)| name.eq_ignore_ascii_case(marker))?;
+
+    match events.get(end) {
+        Some((Event::SoftBreak | Event::HardBreak, _)) => {
+            events.drain(1..=end);
+        }
+        Some((Event::End(TagEnd::Paragraph), _)) => {
+            events.drain(0..=end);
+        }
+        _ => return None,
+    }
+
+    Some((class, title))
+}
+
+/// `split_html_lines(html)` splits highlighted html into lines.
+/// The spans still open at the end of a line are closed,
+/// and opened again at the start of the next one, so that each line is valid html.
+/// The newlines are removed.
+pub fn split_html_lines(html: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut open_tags: Vec<&str> = Vec::new();
+    let mut line = String::new();
+    let mut has_text = false;
+
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '<' => {
+                let end = rest.find('>').map_or(rest.len(), |i| i + 1);
+                let tag = &rest[..end];
+                match tag.starts_with("</") {
+                    true => open_tags.pop(),
+                    false => {
+                        open_tags.push(tag);
+                        None
+                    }
+                };
+                line.push_str(tag);
+                rest = &rest[end..];
+            }
+            '\n' => {
+                line.push_str(&"</span>".repeat(open_tags.len()));
+                lines.push(std::mem::take(&mut line));
+                line.push_str(&open_tags.concat());
+                has_text = false;
+                rest = &rest[1..];
+            }
+            c => {
+                line.push(c);
+                has_text = true;
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    if has_text {
+        line.push_str(&"</span>".repeat(open_tags.len()));
+        lines.push(line)
+    }
+    lines
+}
+
+/// the parsed info string of a fenced code block,
+/// for example "rust {2,4-6}"
+#[derive(Default, Debug)]
+pub struct CodeInfo {
+    /// the language of the code, used for syntax highlighting
+    pub lang: String,
+
+    /// the title of the code block, like a file name
+    pub title: Option<String>,
+
+    /// the lines to emphasize, starting from 1
+    pub highlighted_lines: Vec<RangeInclusive<usize>>,
+}
+
+impl CodeInfo {
+    /// `is_highlighted(n)` tells wether the line `n` should be emphasized
+    pub fn is_highlighted(&self, n: usize) -> bool {
+        self.highlighted_lines.iter().any(|r| r.contains(&n))
+    }
+}
+
+/// `is_unclosed_fence(block)` tells wether the source `block` of a fenced code block
+/// has no closing fence, which happens at the end of a document that is still being written
+pub fn is_unclosed_fence(block: &str) -> bool {
+    let mut lines = block
+        .lines()
+        .map(|line| line.trim_start_matches([' ', '>']).trim_end())
+        .filter(|line| !line.is_empty());
+    let Some(open) = lines.next() else {
+        return false;
+    };
+    let fence_char = open.chars().next();
+    let fence = open.chars().take_while(|&c| Some(c) == fence_char).count();
+    match lines.last() {
+        Some(close) => !(close.len() >= fence && close.chars().all(|c| Some(c) == fence_char)),
+        None => true,
+    }
+}
+
+/// `parse_info_string(info)` splits the info string of a fenced code block
+/// into the language, an optional title (see [`parse_title`]) and an optional `{...}`
+/// line range spec, like `{2,4-6}` to emphasize the lines 2, 4, 5 and 6.
+pub fn parse_info_string(info: &str) -> CodeInfo {
+    let info = info.trim();
+    let lang_end = info
+        .find(|c: char| c.is_whitespace() || c == '{' || c == ':')
+        .unwrap_or(info.len());
+    let (lang, rest) = info.split_at(lang_end);
+
+    let highlighted_lines = match (rest.find('{'), rest.find('}')) {
+        (Some(start), Some(end)) if start < end => parse_line_ranges(&rest[start + 1..end]),
+        _ => Vec::new(),
+    };
+
+    CodeInfo {
+        lang: lang.to_string(),
+        title: parse_title(rest),
+        highlighted_lines,
+    }
+}
+
+/// `parse_title(rest)` finds the title in the info string `rest`, after the language.
+/// It is written like `:src/main.rs`, `title=src/main.rs` or `title="src/main.rs"`
+fn parse_title(rest: &str) -> Option<String> {
+    let until_space = |s: &str| s[..s.find(char::is_whitespace).unwrap_or(s.len())].to_string();
+    let title = match rest.strip_prefix(':') {
+        Some(title) => until_space(title),
+        None => {
+            let title = &rest[rest.find("title=")? + "title=".len()..];
+            match title.strip_prefix('"') {
+                Some(quoted) => quoted[..quoted.find('"')?].to_string(),
+                None => until_space(title),
+            }
+        }
+    };
+    (!title.is_empty()).then_some(title)
+}
+
+/// `parse_line_ranges(spec)` parses a comma separated list of
+/// line numbers and ranges, like `2,4-6`.
+/// Invalid items are ignored.
+fn parse_line_ranges(spec: &str) -> Vec<RangeInclusive<usize>> {
+    spec.split(',')
+        .filter_map(|item| match item.split_once('-') {
+            Some((start, end)) => Some(start.trim().parse().ok()?..=end.trim().parse().ok()?),
+            None => {
+                let n = item.trim().parse().ok()?;
+                Some(n..=n)
+            }
+        })
+        .collect()
+}
+
+/// `merge_text(events, source)` merges the consecutive text events of `events`,
+/// since pulldown_cmark sometimes splits a text in several events.
+/// Texts starting with an escaped character are kept apart
+pub fn merge_text<'a>(
+    events: Vec<(Event<'a>, Range<usize>)>,
+    source: &str,
+) -> Vec<(Event<'a>, Range<usize>)> {
+    let mut merged: Vec<(Event<'a>, Range<usize>)> = Vec::with_capacity(events.len());
+    for (event, range) in events {
+        if let (Some((Event::Text(previous), previous_range)), Event::Text(text)) =
+            (merged.last_mut(), &event)
+        {
+            if previous_range.end == range.start && !source[range.start..].starts_with('\\') {
+                *previous = format!("{previous}{text}").into();
+                previous_range.end = range.end;
+                continue;
+            }
+        }
+        merged.push((event, range));
+    }
+    merged
+}
+
+/// `block_key(parts, seen)` gives the key of a block of a document, from the hash of
+/// `parts` and the number of blocks with the same hash before it, counted in `seen`.
+/// The `parts` are all that changes how the block is rendered, so that a block
+/// keeps its key, and so its DOM, as long as it would be rendered the same
+pub fn block_key(parts: impl Hash, seen: &mut HashMap<u64, usize>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    parts.hash(&mut hasher);
+    let hash = hasher.finish();
+    let occurrence = seen.entry(hash).or_insert(0);
+    *occurrence += 1;
+    let mut hasher = DefaultHasher::new();
+    (hash, *occurrence).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// the html elements put on their own line by [`pretty_html`]
+#[cfg(feature = "ssr")]
+const BLOCK_ELEMENTS: [&str; 33] = [
+    "address", "article", "aside", "blockquote", "details", "div", "dl", "figcaption", "figure",
+    "footer", "h1", "h2", "h3", "h4", "h5", "h6", "header", "hr", "li", "nav", "ol", "p", "pre",
+    "section", "summary", "table", "tbody", "td", "tfoot", "th", "thead", "tr", "ul",
+];
+
+/// `pretty_html(html)` indents `html`, with each block element on its own line,
+/// so that generated pages are easy to read and to diff.
+/// Inline elements and the content of `<pre>` are kept as they are,
+/// so the rendered page does not change
+#[cfg(feature = "ssr")]
+pub fn pretty_html(html: &str) -> String {
+    let mut pretty = String::with_capacity(html.len());
+    // for each open block element, wether it contains other blocks
+    let mut blocks: Vec<bool> = Vec::new();
+    let mut in_pre = false;
+    let mut rest = html;
+    while !rest.is_empty() {
+        let end = match rest.starts_with('<') {
+            true if rest.starts_with("<!--") => rest.find("-->").map_or(rest.len(), |i| i + 3),
+            true => rest.find('>').map_or(rest.len(), |i| i + 1),
+            false => rest.find('<').unwrap_or(rest.len()),
+        };
+        let (token, after) = rest.split_at(end);
+        rest = after;
+
+        let name = token
+            .strip_prefix('<')
+            .map(|t| t.trim_start_matches('/'))
+            .map(|t| t.split([' ', '>', '/']).next().unwrap_or("").to_ascii_lowercase());
+        let is_block = name.as_deref().is_some_and(|n| BLOCK_ELEMENTS.contains(&n));
+        let closing = token.starts_with("</");
+        let is_pre = name.as_deref() == Some("pre");
+
+        if !is_block || (in_pre && !(closing && is_pre)) {
+            pretty.push_str(token);
+            continue;
+        }
+        if closing {
+            in_pre = false;
+            if blocks.pop() == Some(true) {
+                new_line(&mut pretty, blocks.len());
+            }
+        } else {
+            if let Some(parent) = blocks.last_mut() {
+                *parent = true
+            }
+            if !pretty.is_empty() {
+                new_line(&mut pretty, blocks.len());
+            }
+            // `<hr>` is never closed
+            if name.as_deref() != Some("hr") {
+                blocks.push(false)
+            }
+            in_pre = is_pre;
+        }
+        pretty.push_str(token);
+    }
+    pretty
+}
+
+#[cfg(feature = "ssr")]
+fn new_line(html: &mut String, depth: usize) {
+    html.push('\n');
+    html.push_str(&"  ".repeat(depth));
+}
+
+/// the html elements that can not have content, so they are never closed
+const VOID_ELEMENTS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param",
+    "source", "track", "wbr",
+];
+
+/// `html_depth(html)` tells how the html tag `html` changes the nesting of elements:
+/// `1` for an opening tag, `-1` for a closing tag, and `0` for
+/// self-closing tags, void elements and comments
+pub fn html_depth(html: &str) -> i32 {
+    let html = html.trim();
+    if html.starts_with("</") {
+        return -1;
+    }
+    let special = html.starts_with("<!") || html.starts_with("<?") || html.ends_with("/>");
+    if !html.starts_with('<') || special {
+        return 0;
+    }
+    let name = html[1..]
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match VOID_ELEMENTS.contains(&name.as_str()) {
+        true => 0,
+        false => 1,
+    }
+}
+
+/// `escape_html(text)` escapes the special characters of html in `text`,
+/// so that it can be put in text content as well as in quoted attribute values
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// `find_autolinks(text)` finds the bare urls in `text`, like `https://example.com`
+/// or `www.example.com`, and returns their ranges.
+/// Trailing punctuation and unbalanced closing parenthesis are not part of the urls
+pub fn find_autolinks(text: &str) -> Vec<Range<usize>> {
+    let mut links = Vec::new();
+    let mut start = 0;
+    while start < text.len() {
+        let rest = &text[start..];
+        let found = ["https://", "http://", "www."]
+            .iter()
+            .filter_map(|prefix| rest.find(prefix).map(|i| (i, prefix.len())))
+            .min();
+        let Some((offset, prefix_len)) = found else {
+            break;
+        };
+
+        let link_start = start + offset;
+        // `awww.example.com` or `xhttp://` are not links
+        let at_word_start = text[..link_start]
+            .chars()
+            .next_back()
+            .map_or(true, |c| !c.is_alphanumeric());
+        let link_len = autolink_len(&text[link_start..]);
+        if at_word_start && link_len > prefix_len {
+            links.push(link_start..link_start + link_len);
+            start = link_start + link_len;
+        } else {
+            start = link_start + prefix_len;
+        }
+    }
+    links
+}
+
+/// `find_emails(text)` finds the bare email addresses in `text`,
+/// like `hello@example.com`, and returns their ranges
+pub fn find_emails(text: &str) -> Vec<Range<usize>> {
+    let is_local = |c: char| c.is_ascii_alphanumeric() || ".%+-_".contains(c);
+    let is_domain = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '.';
+
+    let mut emails = Vec::new();
+    let mut previous_end = 0;
+    for (at, _) in text.match_indices('@') {
+        if at < previous_end {
+            continue;
+        }
+        // the character before the address may take several bytes
+        let start = text[..at]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !is_local(*c))
+            .map_or(0, |(i, c)| i + c.len_utf8())
+            .max(previous_end);
+        let domain = &text[at + 1..];
+        let domain_len = domain.find(|c: char| !is_domain(c)).unwrap_or(domain.len());
+        // a trailing dot ends the sentence, not the domain
+        let domain = domain[..domain_len].trim_end_matches(['.', '-']);
+        let valid_domain = domain.contains('.') && !domain.starts_with(['.', '-']);
+        if start < at && valid_domain {
+            let end = at + 1 + domain.len();
+            emails.push(start..end);
+            previous_end = end;
+        }
+    }
+    emails
+}
+
+/// `autolink_len(text)` gives the length of the url at the start of `text`
+fn autolink_len(text: &str) -> usize {
+    let mut end = text
+        .find(|c: char| c.is_whitespace() || c == '<')
+        .unwrap_or(text.len());
+    loop {
+        let url = &text[..end];
+        match url.chars().next_back() {
+            Some(c @ ('.' | ',' | ':' | ';' | '!' | '?' | '"' | '\'' | '*' | '_' | '~')) => {
+                end -= c.len_utf8()
+            }
+            Some(')') if url.matches(')').count() > url.matches('(').count() => end -= 1,
+            _ => return end,
+        }
+    }
+}
+
+/// `take_image_size(s)` splits a trailing image size, like `=200x100`, `=200` or `=x100`,
+/// from `s`. Returns the rest of `s`, and the width and height.
+/// Sizes without a unit are in pixels, other units like `50%` are kept
+pub fn take_image_size(s: &str) -> (&str, ImageSize) {
+    let Some((rest, size)) = s.trim_end().rsplit_once('=') else {
+        return (s, ImageSize::default());
+    };
+    if !(rest.is_empty() || rest.ends_with(char::is_whitespace)) {
+        return (s, ImageSize::default());
+    }
+    let (width, height) = size.split_once('x').unwrap_or((size, ""));
+    let is_size = |x: &str| {
+        x.is_empty()
+            || (x.starts_with(|c: char| c.is_ascii_digit())
+                && x.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '%'))
+    };
+    if size.is_empty() || !is_size(width) || !is_size(height) {
+        return (s, ImageSize::default());
+    }
+    let size = ImageSize {
+        width: (!width.is_empty()).then(|| width.to_string()),
+        height: (!height.is_empty()).then(|| height.to_string()),
+    };
+    (rest.trim_end(), size)
+}
+
+/// `shift_heading(level, offset)` gives the heading level `offset` levels below `level`,
+/// or `H6` if it would be too low
+pub fn shift_heading(level: HeadingLevel, offset: u8) -> HeadingLevel {
+    use HeadingLevel::*;
+    let levels = [H1, H2, H3, H4, H5, H6];
+    let index = levels.iter().position(|x| *x == level).unwrap_or(0);
+    levels[(index + offset as usize).min(5)]
+}
+
+/// `is_internal_url(url, host)` checks if `url` points to the site served from `host`:
+/// relative urls, fragments, and absolute urls with the same host
+pub fn is_internal_url(url: &str, host: &str) -> bool {
+    let rest = match url.split_once("://") {
+        Some((scheme, rest)) if !scheme.contains(['/', '?', '#']) => rest,
+        // the scheme of protocol-relative urls, like `//example.com`, is the one of the page
+        _ if url.starts_with("//") => &url[2..],
+        // urls like `mailto:someone@example.com` or `tel:123`
+        _ if url.split_once(':').is_some_and(|(scheme, _)| is_scheme(scheme)) => return false,
+        _ => return true,
+    };
+    let url_host = rest.split(['/', '?', '#']).next().unwrap_or("");
+    url_host.eq_ignore_ascii_case(host)
+}
+
+/// `resolve_url(url, base)` resolves the relative `url` against the url `base`,
+/// like `./img/logo.png` against `https://example.com/docs/`.
+/// Absolute urls, protocol-relative urls and fragments are given back unchanged.
+/// Like in browsers, the last segment of `base` is a file unless it ends with `/`
+pub fn resolve_url(url: &str, base: &str) -> String {
+    let is_absolute = url.starts_with("//")
+        || url.split_once(':').is_some_and(|(scheme, _)| is_scheme(scheme));
+    if is_absolute || url.is_empty() || url.starts_with('#') {
+        return url.to_string();
+    }
+
+    // the query and fragment of the base are not part of its path
+    let base = base.split(['?', '#']).next().unwrap_or("");
+    let (origin, base_path) = match base.find("//") {
+        Some(i) if base[..i].is_empty() || base[..i].ends_with(':') => {
+            let host_end = base[i + 2..].find('/').map_or(base.len(), |j| i + 2 + j);
+            base.split_at(host_end)
+        }
+        _ => ("", base),
+    };
+
+    let (path, suffix) = url.split_at(url.find(['?', '#']).unwrap_or(url.len()));
+    let path = match (path.starts_with('/'), base_path.rfind('/')) {
+        // a query alone, like `?page=2`, is for the page of the base itself
+        _ if path.is_empty() && (origin.is_empty() || !base_path.is_empty()) => {
+            base_path.to_string()
+        }
+        _ if path.is_empty() => "/".to_string(),
+        (true, _) => path.to_string(),
+        (false, Some(i)) => format!("{}{path}", &base_path[..=i]),
+        (false, None) if origin.is_empty() => path.to_string(),
+        (false, None) => format!("/{path}"),
+    };
+
+    // `.` and `..` segments are removed, without going above the root
+    let parts: Vec<&str> = path.split('/').collect();
+    let mut segments = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        match *part {
+            "." | ".." => {
+                if *part == ".." && segments.last().is_some_and(|s: &&str| !s.is_empty()) {
+                    segments.pop();
+                }
+                // `docs/..` is a directory
+                if i == parts.len() - 1 {
+                    segments.push("")
+                }
+            }
+            part => segments.push(part),
+        }
     }
+    format!("{origin}{}{suffix}", segments.join("/"))
+}
+
+fn is_scheme(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+}
+
+/// the url schemes that run scripts, or show a page made by the link
+const UNSAFE_SCHEMES: [&str; 3] = ["javascript", "vbscript", "data"];
+
+/// `is_unsafe_url(url)` checks if `url` runs a script when it is opened,
+/// like `javascript:alert(1)`. Like browsers, the case of the scheme
+/// and the whitespace and control characters in it are ignored
+pub fn is_unsafe_url(url: &str) -> bool {
+    let Some((scheme, _)) = url.split_once(':') else {
+        return false;
+    };
+    let scheme: String = scheme
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect();
+    UNSAFE_SCHEMES.iter().any(|s| scheme.eq_ignore_ascii_case(s))
 }
 
 #[derive(Clone)]
@@ -47,6 +678,34 @@ where
     }
 }
 
+/// a function rewriting the events of a document, with their range in the source,
+/// before they are rendered.
+/// Create it with [`EventTransform::new`], so the closure accepts events of any lifetime:
+/// ```ignore
+/// EventTransform::new(|events| events.into_iter().filter(|(e, _)| *e != Event::Rule).collect())
+/// ```
+#[derive(Clone)]
+pub struct EventTransform(Rc<EventTransformFn>);
+
+type EventTransformFn =
+    dyn for<'a> Fn(Vec<(Event<'a>, Range<usize>)>) -> Vec<(Event<'a>, Range<usize>)>;
+
+impl EventTransform {
+    pub fn new<F>(f: F) -> Self
+    where
+        F: for<'a> Fn(Vec<(Event<'a>, Range<usize>)>) -> Vec<(Event<'a>, Range<usize>)> + 'static,
+    {
+        EventTransform(Rc::new(f))
+    }
+
+    pub fn call<'a>(
+        &self,
+        events: Vec<(Event<'a>, Range<usize>)>,
+    ) -> Vec<(Event<'a>, Range<usize>)> {
+        self.0(events)
+    }
+}
+
 #[derive(Clone)]
 pub struct HtmlCallback<In>(Rc<dyn Fn(In) -> HtmlElement<AnyElement>>);
 
@@ -73,3 +732,440 @@ where
         HtmlCallback::new(value)
     }
 }
+
+/// like [`HtmlCallback`], but the callback can return `None`
+/// to let the default rendering happen
+#[derive(Clone)]
+pub struct OptionalHtmlCallback<In>(Rc<dyn Fn(In) -> Option<HtmlElement<AnyElement>>>);
+
+impl<In> OptionalHtmlCallback<In> {
+    pub fn new<F, H>(f: F) -> Self
+    where
+        H: ElementDescriptor + 'static,
+        F: Fn(In) -> Option<HtmlElement<H>> + 'static,
+    {
+        OptionalHtmlCallback(Rc::new(move |x| f(x).map(|h| h.into_any())))
+    }
+
+    pub fn call(&self, value: In) -> Option<HtmlElement<AnyElement>> {
+        self.0(value)
+    }
+}
+
+impl<In, D, F> From<F> for OptionalHtmlCallback<In>
+where
+    F: Fn(In) -> Option<HtmlElement<D>> + 'static,
+    D: ElementDescriptor + 'static,
+{
+    fn from(value: F) -> OptionalHtmlCallback<In> {
+        OptionalHtmlCallback::new(value)
+    }
+}
+
+/// `convert_math_delimiters(src)` rewrites the latex delimiters `\(...\)` and `\[...\]`
+/// of `src` into `$...$` and `$$...$$`, so they are parsed as math.
+/// A delimiter is only converted when it is closed before the end of the paragraph,
+/// and delimiters in code spans, code blocks or after an escaped backslash are kept.
+/// Code blocks are found line by line, also in blockquotes and list items.
+/// The [`OffsetMap`] gives back the positions in `src` of the rewritten source
+pub fn convert_math_delimiters(src: &str) -> (String, OffsetMap) {
+    let mut out = String::with_capacity(src.len());
+    let mut offsets = OffsetMap::default();
+    // start of the text, or of the code, that is not copied yet
+    let mut text_start = 0;
+    let mut in_code = false;
+    let mut offset = 0;
+    // the open fence, with its character, its length, and the blockquote depth
+    // and list indentation it was opened in
+    let mut fence: Option<(char, usize, usize, usize)> = None;
+    // the indentation of the content of the current list item, in columns
+    let mut list_indent = 0;
+    // wether an indented code block can start on this line
+    let mut block_start = true;
+
+    for line in src.split_inclusive('\n') {
+        let (quotes, line_rest) = strip_quote_markers(line);
+        let (indent, rest) = indentation(line_rest);
+        let blank = rest.trim().is_empty();
+
+        let mut code = false;
+        if let Some((c, n, fence_quotes, fence_indent)) = fence {
+            if quotes < fence_quotes || (!blank && indent < fence_indent) {
+                // the blockquote or the list item of the fence ended, and the fence with it
+                fence = None;
+                if indent < fence_indent {
+                    list_indent = 0;
+                }
+            } else {
+                code = true;
+                if closes_fence(rest, c, n) {
+                    fence = None;
+                    block_start = true;
+                }
+            }
+        }
+        if !code && !blank {
+            if indent < list_indent && block_start {
+                list_indent = 0;
+            }
+            if block_start && indent >= list_indent + 4 {
+                code = true;
+            } else {
+                let mut rest = rest;
+                let mut indent = indent;
+                while let Some(width) = list_marker_width(rest) {
+                    list_indent = indent + width;
+                    indent = list_indent;
+                    rest = &rest[width..];
+                }
+                if let Some((c, n)) = fence_marker(rest) {
+                    if indent < list_indent {
+                        // a fence that is less indented than the list item is after the list
+                        list_indent = 0;
+                    }
+                    fence = Some((c, n, quotes, list_indent));
+                    code = true;
+                }
+                block_start = false;
+            }
+        } else if blank && fence.is_none() {
+            block_start = true;
+        }
+
+        if code != in_code {
+            copy_text(src, text_start..offset, in_code, &mut out, &mut offsets);
+            text_start = offset;
+            in_code = code;
+        }
+        offset += line.len();
+    }
+    copy_text(src, text_start..src.len(), in_code, &mut out, &mut offsets);
+    (out, offsets)
+}
+
+/// `copy_text(src, range, code, out, offsets)` writes `src[range]` at the end of `out`,
+/// as it is if it is `code`, else with its delimiters converted
+fn copy_text(
+    src: &str,
+    range: Range<usize>,
+    code: bool,
+    out: &mut String,
+    offsets: &mut OffsetMap,
+) {
+    match code {
+        true => out.push_str(&src[range]),
+        false => convert_inline(src, range, out, offsets),
+    }
+}
+
+/// `strip_quote_markers(line)` gives the number of `>` markers at the start of `line`,
+/// and the rest of the line after them
+fn strip_quote_markers(mut line: &str) -> (usize, &str) {
+    let mut quotes = 0;
+    loop {
+        let (indent, rest) = indentation(line);
+        match rest.strip_prefix('>') {
+            Some(rest) if indent < 4 => {
+                quotes += 1;
+                line = rest.strip_prefix(' ').unwrap_or(rest);
+            }
+            _ => return (quotes, line),
+        }
+    }
+}
+
+/// `indentation(line)` gives the indentation of `line` in columns,
+/// with tabs stopping at multiples of 4, and the rest of the line
+fn indentation(line: &str) -> (usize, &str) {
+    let mut columns = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            ' ' => columns += 1,
+            '\t' => columns += 4 - columns % 4,
+            _ => return (columns, &line[i..]),
+        }
+    }
+    (columns, "")
+}
+
+/// `list_marker_width(line)` gives the width of the list marker at the start of `line`,
+/// like `- ` or `12. `, with the spaces after it
+fn list_marker_width(line: &str) -> Option<usize> {
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let marker = match digits {
+        0 if line.starts_with(['-', '+', '*']) => 1,
+        1..=9 if line[digits..].starts_with(['.', ')']) => digits + 1,
+        _ => return None,
+    };
+    let after = &line[marker..];
+    let spaces = after.len() - after.trim_start_matches(' ').len();
+    match spaces {
+        // an item with indented code, or with nothing, has its content after one space
+        _ if after[spaces..].trim().is_empty() => Some(marker + spaces.min(1)),
+        1..=4 => Some(marker + spaces),
+        0 => None,
+        _ => Some(marker + 1),
+    }
+}
+
+/// `fence_marker(line)` gives the character and the length of the opening fence
+/// at the start of `line`, if there is one
+fn fence_marker(line: &str) -> Option<(char, usize)> {
+    let c = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let n = line.len() - line.trim_start_matches(c).len();
+    // the info string of a backtick fence has no backtick
+    (n >= 3 && !(c == '`' && line[n..].contains('`'))).then_some((c, n))
+}
+
+/// `closes_fence(line, c, n)` tells wether `line` closes a fence of `n` characters `c`
+fn closes_fence(line: &str, c: char, n: usize) -> bool {
+    let rest = line.trim_start_matches(c);
+    line.len() - rest.len() >= n && rest.trim().is_empty()
+}
+
+/// the positions in a source rewritten by [`convert_math_delimiters`]
+#[derive(Default, Debug)]
+pub struct OffsetMap {
+    /// the positions in the rewritten source from which the shift from the original
+    /// source changes, each with the matching position in the original source
+    shifts: Vec<(usize, usize)>,
+}
+
+impl OffsetMap {
+    /// `original(pos)` gives the position in the original source
+    /// of the position `pos` in the rewritten source
+    pub fn original(&self, pos: usize) -> usize {
+        match self.shifts.partition_point(|(rewritten, _)| *rewritten <= pos) {
+            0 => pos,
+            i => {
+                let (rewritten, original) = self.shifts[i - 1];
+                original + (pos - rewritten)
+            }
+        }
+    }
+}
+
+/// `convert_inline(src, range, out, offsets)` converts the delimiters of `src[range]`,
+/// which has no code block, and writes it at the end of `out`
+fn convert_inline(src: &str, range: Range<usize>, out: &mut String, offsets: &mut OffsetMap) {
+    let text = &src[range.clone()];
+    // the position of `rest` in `src`
+    let position = |rest: &str| range.end - rest.len();
+    let mut rest = text;
+    while let Some(i) = rest.find(['\\', '`']) {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if rest.starts_with('`') {
+            // copy the code span as it is
+            let ticks = rest.len() - rest.trim_start_matches('`').len();
+            let after = &rest[ticks..];
+            let end = find_ticks(after, ticks).map_or(ticks, |j| ticks + j + ticks);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        let (open, close, dollars) = match rest.get(..2) {
+            Some("\\(") => ("\\(", "\\)", "$"),
+            Some("\\[") => ("\\[", "\\]", "$$"),
+            _ => {
+                // an escaped character, like `\\` or `\$`, is copied with its backslash
+                let len = rest[1..].chars().next().map_or(0, char::len_utf8) + 1;
+                out.push_str(&rest[..len]);
+                rest = &rest[len..];
+                continue;
+            }
+        };
+        let body = &rest[open.len()..];
+        let paragraph = body.find("\n\n").unwrap_or(body.len());
+        match body[..paragraph].find(close) {
+            Some(j) => {
+                out.push_str(dollars);
+                offsets.shifts.push((out.len(), position(body)));
+                out.push_str(&body[..j]);
+                out.push_str(dollars);
+                rest = &body[j + close.len()..];
+                offsets.shifts.push((out.len(), position(rest)));
+            }
+            None => {
+                out.push_str(open);
+                rest = body;
+            }
+        }
+    }
+    out.push_str(rest);
+}
+
+/// position of the next run of exactly `n` backticks in `text`
+fn find_ticks(text: &str, n: usize) -> Option<usize> {
+    let mut i = 0;
+    while let Some(j) = text[i..].find('`') {
+        let start = i + j;
+        let len = text[start..].len() - text[start..].trim_start_matches('`').len();
+        if len == n {
+            return Some(start);
+        }
+        i = start + len;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_bare_emails() {
+        let text = "write to hello@example.com.";
+        let emails = find_emails(text);
+        assert_eq!(emails, vec![9..26]);
+        assert_eq!(&text[emails[0].clone()], "hello@example.com");
+    }
+
+    #[test]
+    fn finds_emails_after_multibyte_characters() {
+        // like in GFM, the local part is ascii, so `josé` is not an address
+        assert_eq!(find_emails("écrivez à josé@example.com"), vec![]);
+        let text = "écrivez àbob@example.com";
+        let emails = find_emails(text);
+        assert_eq!(emails.len(), 1);
+        assert_eq!(&text[emails[0].clone()], "bob@example.com");
+    }
+
+    #[test]
+    fn identical_blocks_get_different_keys() {
+        let mut seen = HashMap::new();
+        let keys: Vec<_> = ["a", "b", "a"].iter().map(|b| block_key(b, &mut seen)).collect();
+        assert_ne!(keys[0], keys[2]);
+
+        // the keys only depend on the blocks before
+        let mut seen = HashMap::new();
+        assert_eq!(block_key("a", &mut seen), keys[0]);
+        assert_eq!(block_key("a", &mut seen), keys[2]);
+    }
+
+    #[test]
+    fn math_delimiters_keep_the_source_positions() {
+        let src = r"a \(x^2\) b \[y\] `\(c\)`";
+        let (out, offsets) = convert_math_delimiters(src);
+        assert_eq!(out, r"a $x^2$ b $$y$$ `\(c\)`");
+
+        let x = out.find('x').unwrap();
+        assert_eq!(&src[offsets.original(x)..offsets.original(x + 3)], "x^2");
+        // the whole inline math, with its delimiters
+        assert_eq!(&src[offsets.original(2)..offsets.original(7)], r"\(x^2\)");
+        assert_eq!(&src[offsets.original(10)..offsets.original(15)], r"\[y\]");
+        assert_eq!(offsets.original(out.len()), src.len());
+    }
+
+    #[test]
+    fn math_delimiters_in_indented_code_are_kept() {
+        let src = "    \\(x\\)\n";
+        assert_eq!(convert_math_delimiters(src).0, src);
+        // a paragraph can not be interrupted by indented code
+        assert_eq!(convert_math_delimiters("a\n    \\(x\\)\n").0, "a\n    $x$\n");
+        // the content of a list item is indented under its marker
+        assert_eq!(convert_math_delimiters("- a\n\n    \\(x\\)\n").0, "- a\n\n    $x$\n");
+        let src = "- a\n\n      \\(x\\)\n";
+        assert_eq!(convert_math_delimiters(src).0, src);
+    }
+
+    #[test]
+    fn math_delimiters_in_quoted_or_nested_fences_are_kept() {
+        for src in [
+            "> ```\n> \\(x\\)\n> ```\n",
+            "- a\n  - b\n    ```\n    \\(x\\)\n    ```\n",
+            "- ```\n  \\(x\\)\n  ```\n",
+            // a tilde fence is not closed by backticks
+            "~~~\n```\n\\(x\\)\n~~~\n",
+        ] {
+            assert_eq!(convert_math_delimiters(src).0, src);
+        }
+        // the fence ends with its blockquote
+        let src = "> ```\n> \\(x\\)\n\n\\(y\\)";
+        assert_eq!(convert_math_delimiters(src).0, "> ```\n> \\(x\\)\n\n$y$");
+    }
+
+    #[test]
+    fn fences_are_closed_by_the_same_character() {
+        assert!(!is_unclosed_fence("```\ncode\n```"));
+        assert!(!is_unclosed_fence("~~~\ncode\n~~~~"));
+        assert!(is_unclosed_fence("~~~\ncode\n```"));
+        assert!(is_unclosed_fence("````\ncode\n```"));
+        assert!(is_unclosed_fence("```\ncode"));
+    }
+
+    #[test]
+    fn fences_in_blockquotes_are_closed() {
+        assert!(!is_unclosed_fence("> ```\n> code\n> ```"));
+        assert!(!is_unclosed_fence(">> ~~~\n>> code\n>> ~~~"));
+        assert!(is_unclosed_fence("> ```\n> code"));
+    }
+
+    #[test]
+    fn image_sizes_are_taken_from_the_end_of_titles() {
+        let size = |width: &str, height: &str| ImageSize {
+            width: (!width.is_empty()).then(|| width.to_string()),
+            height: (!height.is_empty()).then(|| height.to_string()),
+        };
+        assert_eq!(take_image_size("a cat =200x100"), ("a cat", size("200", "100")));
+        assert_eq!(take_image_size("a cat =50%"), ("a cat", size("50%", "")));
+        assert_eq!(take_image_size("=x100"), ("", size("", "100")));
+        // an `=` that is not a size stays in the title
+        assert_eq!(take_image_size("a=b"), ("a=b", ImageSize::default()));
+        assert_eq!(take_image_size("x =2 cats"), ("x =2 cats", ImageSize::default()));
+    }
+
+    #[test]
+    fn alert_markers_are_case_insensitive() {
+        let mut events = vec![
+            (Event::Start(Tag::Paragraph), 2..16),
+            (Event::Text("[!note]".into()), 2..9),
+            (Event::SoftBreak, 9..10),
+            (Event::Text("text".into()), 12..16),
+            (Event::End(TagEnd::Paragraph), 2..16),
+        ];
+        assert_eq!(take_alert_marker(&mut events), Some(("note", "Note")));
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1].0, Event::Text("text".into()));
+
+        let mut events = vec![
+            (Event::Start(Tag::Paragraph), 0..9),
+            (Event::Text("[!nope]".into()), 0..9),
+            (Event::End(TagEnd::Paragraph), 0..9),
+        ];
+        assert_eq!(take_alert_marker(&mut events), None);
+        assert_eq!(events.len(), 3);
+    }
+
+    #[test]
+    fn resolves_relative_urls() {
+        let base = "https://example.com/docs/page.html";
+        assert_eq!(resolve_url("./img/logo.png", base), "https://example.com/docs/img/logo.png");
+        assert_eq!(resolve_url("../a.png", base), "https://example.com/a.png");
+        // `..` does not go above the root
+        assert_eq!(resolve_url("../../../a.png", base), "https://example.com/a.png");
+        assert_eq!(resolve_url("img.png", "https://example.com"), "https://example.com/img.png");
+        assert_eq!(resolve_url("?page=2", base), "https://example.com/docs/page.html?page=2");
+        assert_eq!(resolve_url("?page=2", "https://example.com"), "https://example.com/?page=2");
+    }
+
+    #[test]
+    fn keeps_absolute_urls_and_fragments() {
+        let base = "https://example.com/docs/";
+        for url in ["#intro", "https://other.org/a", "//cdn.example.org/a.js", "mailto:a@b.org"] {
+            assert_eq!(resolve_url(url, base), url);
+        }
+    }
+
+    #[test]
+    fn finds_unsafe_urls() {
+        assert!(is_unsafe_url("javascript:alert(1)"));
+        assert!(is_unsafe_url(" JavaScript:alert(1)"));
+        assert!(is_unsafe_url("java\tscript:alert(1)"));
+        assert!(is_unsafe_url("vbscript:msgbox"));
+        assert!(is_unsafe_url("data:text/html,<script>alert(1)</script>"));
+        assert!(!is_unsafe_url("https://example.com/javascript:"));
+        assert!(!is_unsafe_url("mailto:someone@example.com"));
+        assert!(!is_unsafe_url("page#data:1"));
+    }
+}
@@ -0,0 +1,57 @@
+//! helpers shared by the rendering tests, that render markdown to an html string
+#![allow(dead_code)]
+
+use leptos::{create_runtime, IntoView};
+use leptos_markdown::{markdown_to_html, RenderContext, RenderContextBuilder};
+use pulldown_cmark_wikilink::Options;
+
+/// `render(src)` renders the markdown `src` with the default options, see [`clean`]
+pub fn render(src: &str) -> String {
+    render_with(src, Options::all(), RenderContext::builder())
+}
+
+/// `render_with(src, options, context)` renders the markdown `src`,
+/// parsed with `options` and rendered with `context`, see [`clean`]
+pub fn render_with(src: &str, options: Options, context: RenderContextBuilder) -> String {
+    let html = markdown_to_html(src, options, context, false)
+        .unwrap_or_else(|e| panic!("could not render {src:?}: {}", e.message()));
+    clean(&html)
+}
+
+/// `render_view(view)` renders the view made by `view` to a string, see [`clean`].
+/// It is used to test the props of the [`Markdown`](leptos_markdown::Markdown) component
+pub fn render_view<V: IntoView>(view: impl FnOnce() -> V) -> String {
+    let runtime = create_runtime();
+    let html = view().into_view().render_to_string().to_string();
+    runtime.dispose();
+    clean(&html)
+}
+
+/// `clean(html)` removes what leptos adds to `html` for the hydration,
+/// the comments and the `data-hk` attributes, so that only the markup is compared
+pub fn clean(html: &str) -> String {
+    let html = remove_between(html, "<!--", "-->");
+    remove_between(&html, " data-hk=\"", "\"")
+}
+
+/// `text(html)` gives the text of `html`, without the tags and with the quotes unescaped
+pub fn text(html: &str) -> String {
+    remove_between(html, "<", ">")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+}
+
+/// `remove_between(s, start, end)` removes from `s` all the parts
+/// starting with `start` and ending with `end`
+fn remove_between(s: &str, start: &str, end: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find(start) {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + start.len()..];
+        rest = after.find(end).map_or("", |j| &after[j + end.len()..]);
+    }
+    out.push_str(rest);
+    out
+}
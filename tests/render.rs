@@ -0,0 +1,397 @@
+//! tests of the html rendered for each kind of element.
+//! They render to a string, so they need the `ssr` feature:
+//! `cargo test --features ssr`
+#![cfg(feature = "ssr")]
+
+use leptos::*;
+use leptos_markdown::{
+    EventTransform, HtmlError, Markdown, Metadata, MetadataKind, RenderContext, TocEntry,
+};
+use pulldown_cmark_wikilink::Options;
+
+mod common;
+use common::{render, render_view, render_with, text};
+
+#[test]
+fn image_alt_is_the_text_and_title_the_tooltip() {
+    let html = render(r#"![a cat](cat.png "meow")"#);
+    assert!(html.contains(r#"alt="a cat""#), "{html}");
+    assert!(html.contains(r#"title="meow""#), "{html}");
+}
+
+#[test]
+fn smart_punctuation_only_when_enabled() {
+    let src = r#""hello" `"code"`"#;
+    let smart = render_view(|| view! { <Markdown src=src.to_string() smart_punctuation=true/> });
+    let smart = text(&smart);
+    assert!(smart.contains("\u{201c}hello\u{201d}"), "{smart}");
+    // code is never changed
+    assert!(smart.contains(r#""code""#), "{smart}");
+
+    let plain = render_view(|| view! { <Markdown src=src.to_string() smart_punctuation=false/> });
+    let plain = text(&plain);
+    assert!(plain.contains(r#""hello""#), "{plain}");
+    assert!(plain.contains(r#""code""#), "{plain}");
+}
+
+#[test]
+fn props_add_their_flags_to_parse_options() {
+    let html = render_view(|| {
+        view! { <Markdown src="a\nb" parse_options=Options::empty() hard_line_breaks=true/> }
+    });
+    assert!(html.contains("<br"), "{html}");
+
+    let html = render_view(|| {
+        view! { <Markdown src="- [x] done" parse_options=Options::empty() read_only_tasks=true/> }
+    });
+    assert!(html.contains(r#"type="checkbox""#), "{html}");
+
+    let html = render_view(|| {
+        view! { <Markdown src="\"a\"" parse_options=Options::empty() smart_punctuation=true/> }
+    });
+    assert!(text(&html).contains("\u{201c}a\u{201d}"), "{html}");
+
+    let html = render_view(|| {
+        view! { <Markdown src=r"\(x\)" parse_options=Options::empty() latex_delimiters=true/> }
+    });
+    assert!(html.contains(r#"class="math""#), "{html}");
+}
+
+#[test]
+fn narrow_parse_options_are_respected() {
+    let html = render_view(|| {
+        view! { <Markdown src="- [x] done" parse_options=Options::empty()/> }
+    });
+    assert!(!html.contains("checkbox"), "{html}");
+    assert!(text(&html).contains("[x] done"), "{html}");
+}
+
+#[test]
+fn ordered_lists_keep_their_start() {
+    let html = render("3. three\n4. four\n");
+    assert!(html.contains(r#"<ol start="3""#), "{html}");
+
+    // like github, a list starting at 1 has no `start`
+    let html = render("1. one\n2. two\n");
+    assert!(html.contains("<ol>"), "{html}");
+}
+
+#[test]
+fn nested_lists_do_not_restart_the_numbering() {
+    let html = render("1. one\n   - a\n   - b\n2. two\n");
+    assert_eq!(html.matches("<ol").count(), 1, "{html}");
+    let sublist = html.find("<ul").unwrap();
+    let sublist_end = html.find("</ul>").unwrap();
+    let two = html.find("two").unwrap();
+    assert!(sublist < sublist_end && sublist_end < two, "{html}");
+    assert!(two < html.find("</ol>").unwrap(), "{html}");
+
+    // nor the paragraphs of a loose list
+    let html = render("5. five\n\n   more\n\n6. six\n");
+    assert_eq!(html.matches("<ol").count(), 1, "{html}");
+    assert!(html.contains(r#"<ol start="5""#), "{html}");
+    assert_eq!(html.matches("<li").count(), 2, "{html}");
+}
+
+#[test]
+fn only_loose_list_items_have_paragraphs() {
+    let tight = render("- a\n- b\n");
+    assert!(!tight.contains("<p"), "{tight}");
+
+    let loose = render("- a\n\n- b\n");
+    assert_eq!(loose.matches("<li><p").count(), 2, "{loose}");
+
+    // a tight item keeps its sublist outside of any paragraph
+    let nested = render("- a\n  - b\n");
+    assert!(!nested.contains("<p"), "{nested}");
+}
+
+#[test]
+fn table_head_cells_are_th() {
+    let html = render("| a | b |\n|---|---|\n| c | d |\n");
+    assert_eq!(html.matches(r#"<th scope="col""#).count(), 2, "{html}");
+    assert_eq!(html.matches("<td").count(), 2, "{html}");
+    let head = &html[html.find("<thead").unwrap()..html.find("</thead>").unwrap()];
+    assert!(!head.contains("<td"), "{html}");
+}
+
+#[test]
+fn table_cells_render_inline_formatting() {
+    let html = render("| **bold** | `code` |\n|---|---|\n| **bold** | `code` |\n");
+    let head_end = html.find("</thead>").unwrap();
+    for part in [&html[..head_end], &html[head_end..]] {
+        assert!(part.contains("<strong>"), "{html}");
+        assert!(text(part).contains("bold"), "{html}");
+        assert!(part.contains("<code>code</code>"), "{html}");
+    }
+}
+
+#[test]
+fn soft_breaks_keep_the_text_around() {
+    let html = render("a\nb");
+    assert_eq!(text(&html), "a\nb", "{html}");
+    assert!(!html.contains("<br"), "{html}");
+}
+
+#[test]
+fn soft_breaks_before_closing_tags_keep_the_next_events() {
+    let html = render("*a\nb* c");
+    assert_eq!(text(&html), "a\nb c", "{html}");
+    assert!(html.contains("<em>"), "{html}");
+
+    let html = render("[a\n](https://example.com) b");
+    let link_end = html.find("</a>").unwrap();
+    assert!(text(&html[..link_end]).contains('a'), "{html}");
+    assert!(text(&html[link_end..]).contains('b'), "{html}");
+}
+
+#[test]
+fn soft_breaks_at_block_boundaries_keep_the_next_blocks() {
+    let html = render("> a\nb\n\nc");
+    let quote_end = html.find("</blockquote>").unwrap();
+    assert!(text(&html[..quote_end]).contains("a\nb"), "{html}");
+    assert!(text(&html[quote_end..]).contains('c'), "{html}");
+
+    let html = render("- a\n  b\n- c\n");
+    assert_eq!(html.matches("<li").count(), 2, "{html}");
+    assert!(text(&html).contains("a\nb"), "{html}");
+    assert!(text(&html).contains('c'), "{html}");
+}
+
+#[test]
+fn huge_code_blocks_are_not_highlighted() {
+    let context = || RenderContext::builder().max_highlight_bytes(Some(1 << 20));
+
+    let small = render_with("```rust\nlet x = 1;\n```", Options::all(), context());
+    assert!(small.contains("<span style="), "{small}");
+
+    let code = "let x = 1;\n".repeat(100_000);
+    let huge = render_with(&format!("```rust\n{code}```"), Options::all(), context());
+    assert!(huge.contains(r#"<pre class="language-rust"><code class="language-rust""#));
+    assert!(!huge.contains("<span style="));
+}
+
+#[test]
+fn emails_become_mailto_links() {
+    let html = render("write to <hello@example.com>");
+    assert!(html.contains(r#"href="mailto:hello@example.com""#), "{html}");
+
+    let src = "écrivez à bob@example.com.";
+    let html = render_view(|| view! { <Markdown src=src.to_string() autolinks=true/> });
+    assert!(html.contains(r#"href="mailto:bob@example.com""#), "{html}");
+}
+
+#[test]
+fn links_are_only_wikilinks_when_wikilinks_are_parsed() {
+    let resolve = |target: String| format!("/wiki/{target}");
+    let html = render_view(|| {
+        view! { <Markdown src="[[a] b](page)" wikilinks=false wikilink_resolver=resolve/> }
+    });
+    assert!(html.contains(r#"href="page""#), "{html}");
+
+    let html = render_view(|| {
+        view! { <Markdown src="[[c]]" wikilinks=true wikilink_resolver=resolve/> }
+    });
+    assert!(html.contains(r#"href="/wiki/c""#), "{html}");
+}
+
+#[test]
+fn text_code_and_link_titles_are_escaped() {
+    let html = render("<script>alert(1)</script> is not run");
+    assert!(!html.contains("<script"), "{html}");
+
+    let html = render("a \\<script>alert(1)\\</script> in text");
+    assert!(!html.contains("<script"), "{html}");
+    assert!(html.contains("&lt;script&gt;"), "{html}");
+
+    let html = render("`<script>alert(1)</script>`");
+    assert!(!html.contains("<script"), "{html}");
+    assert!(html.contains("&lt;script&gt;"), "{html}");
+
+    let html = render(r#"[a](https://example.com "<script>alert(1)</script>")"#);
+    assert!(!html.contains("<script"), "{html}");
+    assert!(html.contains("title="), "{html}");
+}
+
+#[test]
+fn links_with_unsafe_urls_can_not_be_followed() {
+    for url in ["javascript:alert(1)", "VBScript:msgbox(1)", "data:text/html,hi"] {
+        let html = render(&format!("[a]({url})"));
+        assert!(!html.contains("href="), "{html}");
+        assert!(text(&html).contains('a'), "{html}");
+    }
+
+    // and in inline html
+    let html = render("<b>[a](javascript:alert(1))</b>");
+    assert!(!html.contains("javascript:"), "{html}");
+
+    // unless the source is trusted
+    let context = RenderContext::builder().sanitize_html(false);
+    let html = render_with("[a](javascript:alert(1))", Options::all(), context);
+    assert!(html.contains(r#"href="javascript:alert(1)""#), "{html}");
+}
+
+#[test]
+fn tasks_and_math_have_accessible_names() {
+    let html = render("- [ ] buy *milk*\n  - [x] sub task\n");
+    assert!(html.contains(r#"aria-label="buy milk""#), "{html}");
+    assert!(html.contains(r#"aria-label="sub task""#), "{html}");
+
+    let html = render("$x^2$");
+    assert!(html.contains(r#"role="math""#), "{html}");
+    assert!(html.contains(r#"aria-label="x^2""#), "{html}");
+}
+
+#[test]
+fn events_out_of_the_source_are_errors() {
+    let shift = EventTransform::new(|events| {
+        let shift = |r: std::ops::Range<usize>| r.start + 100..r.end + 100;
+        events.into_iter().map(|(e, r)| (e, shift(r))).collect()
+    });
+    let html = render_view(|| {
+        view! { <Markdown src="*a* `b`\n\n- ~c~" transform_events=shift sub_superscript=true/> }
+    });
+    assert!(html.contains(r#"class="error""#), "{html}");
+}
+
+#[test]
+fn metadata_kind_comes_from_the_fences() {
+    let kinds = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    for src in ["---\ntitle: a\n---\ntext", "+++\ntitle = \"a\"\n+++\ntext"] {
+        let kinds = kinds.clone();
+        let on_metadata = move |m: Metadata| kinds.borrow_mut().push(m.kind);
+        let html = render_view(|| view! { <Markdown src=src on_metadata=on_metadata/> });
+        assert!(!html.contains("title"), "{html}");
+    }
+    assert_eq!(*kinds.borrow(), vec![MetadataKind::Yaml, MetadataKind::Toml]);
+}
+
+#[test]
+fn explicit_heading_ids_are_prefixed_and_unique() {
+    let ids = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    let on_toc = {
+        let ids = ids.clone();
+        move |toc: Vec<TocEntry>| *ids.borrow_mut() = toc.into_iter().map(|e| e.id).collect()
+    };
+    let src = "# Title {#custom}\n\n# Other {#custom}\n\n# Plain\n";
+    let html = render_view(|| {
+        view! { <Markdown src=src heading_ids=true heading_id_prefix="doc-" on_toc=on_toc/> }
+    });
+    assert!(html.contains(r#"id="doc-custom""#), "{html}");
+    assert!(html.contains(r#"id="doc-custom-1""#), "{html}");
+    assert!(html.contains(r#"id="doc-plain""#), "{html}");
+    assert!(!text(&html).contains("{#"), "{html}");
+    assert_eq!(*ids.borrow(), vec!["doc-custom", "doc-custom-1", "doc-plain"]);
+}
+
+#[test]
+fn footnote_definitions_are_only_in_the_footnotes() {
+    let html = render("text[^1] and again[^1]\n\n[^1]: the note\n\nafter\n");
+    assert!(!html.contains("<div></div>"), "{html}");
+    let footnotes = html.find(r#"<section class="footnotes">"#).unwrap();
+    assert!(html.find("the note").unwrap() > footnotes, "{html}");
+    assert!(html.find("after").unwrap() < footnotes, "{html}");
+    assert!(html.contains(r##"href="#fnref-1-2""##), "{html}");
+}
+
+#[test]
+fn references_to_missing_footnotes_are_text() {
+    let html = render("a[^missing] b");
+    assert_eq!(text(&html), "a[^missing] b", "{html}");
+    assert!(!html.contains("<li"), "{html}");
+    assert!(!html.contains("footnote"), "{html}");
+}
+
+#[test]
+fn unused_footnotes_are_reported() {
+    let errors = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    let on_error = {
+        let errors = errors.clone();
+        move |e: HtmlError| errors.borrow_mut().push(e)
+    };
+    let src = "a[^used]\n\n[^used]: used\n\n[^unused]: unused\n";
+    let html = render_view(|| view! { <Markdown src=src on_error=on_error/> });
+    assert!(!text(&html).contains("unused"), "{html}");
+    assert_eq!(*errors.borrow(), vec![HtmlError::UnusedFootnote("unused".to_string())]);
+}
+
+/// `containers(src)` renders `src` with the containers enabled
+fn containers(src: &str) -> String {
+    render_view(|| view! { <Markdown src=src.to_string() containers=true/> })
+}
+
+#[test]
+fn containers_can_be_nested() {
+    let html = containers(":::note\nouter\n:::tip\ninner\n:::\n:::\nafter\n");
+    let note = html.find(r#"<div class="note">"#).expect(&html);
+    let tip = html.find(r#"<div class="tip">"#).expect(&html);
+    let after = html.find("after").unwrap();
+    assert!(note < tip && tip < html.find("inner").unwrap(), "{html}");
+    // both containers are closed before the text after them
+    assert_eq!(html[..after].matches("</div>").count(), 2, "{html}");
+    assert!(!text(&html).contains(":::"), "{html}");
+}
+
+#[test]
+fn unclosed_containers_end_with_the_document() {
+    let html = containers(":::note\ntext\n\nmore\n");
+    let note = html.find(r#"<div class="note">"#).expect(&html);
+    let more = html.find("more").unwrap();
+    // the container and the wrapper of the component are closed after the last paragraph
+    assert!(more > note, "{html}");
+    assert_eq!(html[more..].matches("</div>").count(), 2, "{html}");
+}
+
+#[test]
+fn container_markers_are_whole_lines() {
+    let html = containers("before\n:::note\ninside\n:::\n");
+    let note = html.find(r#"<div class="note">"#).expect(&html);
+    assert!(html.find("before").unwrap() < note, "{html}");
+    assert!(html.find("inside").unwrap() > note, "{html}");
+
+    let html = containers("a ::: b and :::note c\n");
+    assert!(!html.contains(r#"<div class="note">"#), "{html}");
+    assert_eq!(text(&html), "a ::: b and :::note c", "{html}");
+}
+
+#[test]
+fn containers_not_allowed_are_text() {
+    let names = vec!["note".to_string()];
+    let html = render_view(|| {
+        view! { <Markdown src=":::warning\ntext\n:::\n" containers=true container_names=names/> }
+    });
+    assert!(!html.contains(r#"class="warning""#), "{html}");
+    assert!(text(&html).contains(":::warning"), "{html}");
+}
+
+#[test]
+fn details_containers_have_a_summary() {
+    let html = containers(":::details More info\nhidden\n:::\n");
+    assert!(html.contains("<summary>More info</summary>"), "{html}");
+    assert!(html.find("hidden").unwrap() > html.find("</summary>").unwrap(), "{html}");
+    assert!(!html.contains("open"), "{html}");
+
+    let html = containers(":::details-open\nshown\n:::\n");
+    assert!(html.contains("<summary>Details</summary>"), "{html}");
+    assert!(html.contains("open"), "{html}");
+}
+
+#[test]
+fn escaped_delimiters_are_not_sub_or_superscript() {
+    let html = render_view(|| {
+        view! { <Markdown src=r"H~2~O, x^2^, H\~2~O and x\^2^" sub_superscript=true/> }
+    });
+    assert_eq!(html.matches("<sub>").count(), 1, "{html}");
+    assert_eq!(html.matches("<sup>").count(), 1, "{html}");
+    assert!(text(&html).contains("H~2~O and x^2^"), "{html}");
+}
+
+#[test]
+fn marks_are_not_split_in_code_spans() {
+    let html = render_view(|| {
+        view! { <Markdown src="`a ==b== c` and ==marked==" highlight_syntax=true/> }
+    });
+    assert!(html.contains("<code>a ==b== c</code>"), "{html}");
+    assert_eq!(html.matches("<mark>").count(), 1, "{html}");
+    assert!(html.contains("<mark>marked</mark>"), "{html}");
+}
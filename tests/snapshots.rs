@@ -0,0 +1,104 @@
+//! snapshot tests: representative documents are rendered to html,
+//! and compared to the html saved in `tests/snapshots`.
+//! A missing snapshot fails the test, and `UPDATE_SNAPSHOTS=1` writes them all,
+//! so that a change of the html is reviewed in the diff of the snapshots.
+//! They need the `ssr` feature, and read the snapshots from the disk, so they run on the host:
+//! `cargo test --features ssr --target x86_64-unknown-linux-gnu`
+#![cfg(all(feature = "ssr", not(target_arch = "wasm32")))]
+
+use std::fs;
+use std::path::PathBuf;
+
+use leptos_markdown::{markdown_to_html, RenderContext};
+use pulldown_cmark_wikilink::Options;
+
+mod common;
+
+/// `check(name, src)` renders the markdown `src`, and compares it to the snapshot `name`
+fn check(name: &str, src: &str) {
+    let html = markdown_to_html(src, Options::all(), RenderContext::builder(), true)
+        .unwrap_or_else(|e| panic!("could not render {name}: {}", e.message()));
+    let html = format!("{}\n", common::clean(&html).trim_end());
+
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let path = dir.join(format!("{name}.html"));
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, html).unwrap();
+        return;
+    }
+    let Ok(expected) = fs::read_to_string(&path) else {
+        panic!("missing snapshot {name}, run with UPDATE_SNAPSHOTS=1")
+    };
+    assert_eq!(
+        html, expected,
+        "the html of {name} changed, run the tests with UPDATE_SNAPSHOTS=1 to accept it"
+    );
+}
+
+#[test]
+fn empty_document() {
+    check("empty", "");
+    check("blank_lines", "\n\n  \n");
+}
+
+#[test]
+fn headings() {
+    check(
+        "headings",
+        "# Title\n\n## Section\n\n### Section\n\nSetext\n======\n\n#### *Emphasis* and `code`\n",
+    );
+}
+
+#[test]
+fn lists() {
+    check("tight_list", "- a\n- b\n- c\n");
+    check("loose_list", "- a\n\n- b\n\n  more\n");
+    check("ordered_list", "3. three\n4. four\n");
+    check("nested_lists", "1. one\n   - a\n     1. deep\n   - b\n2. two\n");
+}
+
+#[test]
+fn tables() {
+    check(
+        "table",
+        "| left | center | right |\n|:-----|:------:|------:|\n| **a** | `b` | c |\n| d | | f |\n",
+    );
+}
+
+#[test]
+fn code() {
+    check("code_block", "```rust\nfn main() {}\n```\n");
+    check("code_block_without_language", "```\nplain <text>\n```\n");
+    check("indented_code_block", "    indented\n    code\n");
+    check("code_span", "some `inline <code>` here\n");
+}
+
+#[test]
+fn links() {
+    check(
+        "links",
+        "[inline](https://example.com \"title\") and [reference][ref], <https://example.org>\n\n\
+         [ref]: /relative\n",
+    );
+    check("images", "![a cat](cat.png \"meow\")\n");
+}
+
+#[test]
+fn math() {
+    check("math", "inline $x^2$ and display\n\n$$\\sum_i x_i$$\n");
+}
+
+#[test]
+fn task_lists() {
+    check("task_list", "- [ ] todo\n- [x] done\n");
+}
+
+#[test]
+fn nested_structures() {
+    check(
+        "nested_structures",
+        "> quote\n>\n> - item with **bold**\n>   > nested quote\n>\n> ```\n> code\n> ```\n",
+    );
+    check("footnotes", "text[^1] and again[^1]\n\n[^1]: the note\n");
+}